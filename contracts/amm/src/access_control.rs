@@ -0,0 +1,145 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen, AccountId};
+
+use crate::events::{OwnershipTransferred, RoleGranted, RoleRevoked};
+use crate::*;
+
+/// Roles the owner can hand out. The owner implicitly holds all of them.
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Runs maintenance on pools.
+    Operator,
+    /// Stops and resumes trading.
+    Pauser,
+    /// Changes the fee config.
+    FeeManager,
+}
+
+pub trait AccessControl {
+    fn get_owner(&self) -> AccountId;
+    fn get_pending_owner(&self) -> Option<AccountId>;
+    fn propose_owner(&mut self, new_owner_id: Option<AccountId>);
+    fn accept_owner(&mut self);
+    fn has_role(&self, role: Role, account_id: AccountId) -> bool;
+    fn get_role_members(&self, role: Role) -> Vec<AccountId>;
+    fn grant_role(&mut self, role: Role, account_id: AccountId);
+    fn revoke_role(&mut self, role: Role, account_id: AccountId);
+    fn renounce_role(&mut self, role: Role);
+}
+
+#[near_bindgen]
+impl AccessControl for Contract {
+    fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    /// First step of an ownership transfer. The new owner takes over once it calls
+    /// `accept_owner`; `None` cancels a pending transfer.
+    #[payable]
+    fn propose_owner(&mut self, new_owner_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.pending_owner_id = new_owner_id;
+    }
+
+    #[payable]
+    fn accept_owner(&mut self) {
+        assert_one_yocto();
+        let new_owner_id = env::predecessor_account_id();
+        require!(
+            self.pending_owner_id.as_ref() == Some(&new_owner_id),
+            "NOT_PENDING_OWNER"
+        );
+        self.pending_owner_id = None;
+        let previous_owner_id = std::mem::replace(&mut self.owner_id, new_owner_id);
+
+        OwnershipTransferred {
+            previous_owner_id: &previous_owner_id,
+            new_owner_id: &self.owner_id,
+        }
+        .emit();
+    }
+
+    fn has_role(&self, role: Role, account_id: AccountId) -> bool {
+        self.internal_has_role(role, &account_id)
+    }
+
+    fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.roles
+            .get(&role)
+            .map(|members| members.to_vec())
+            .unwrap_or_default()
+    }
+
+    #[payable]
+    fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        let mut members = self.roles.get(&role).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::RoleMembers { role }.try_to_vec().unwrap())
+        });
+        if members.insert(&account_id) {
+            self.roles.insert(&role, &members);
+            RoleGranted {
+                role,
+                account_id: &account_id,
+            }
+            .emit();
+        }
+    }
+
+    #[payable]
+    fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_remove_role(role, &account_id);
+    }
+
+    /// Drops a role from the caller.
+    #[payable]
+    fn renounce_role(&mut self, role: Role) {
+        assert_one_yocto();
+        self.internal_remove_role(role, &env::predecessor_account_id());
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_owner(&self) {
+        require!(env::predecessor_account_id() == self.owner_id, "NOT_OWNER");
+    }
+
+    /// Lets the caller through when it is the owner or holds `role`.
+    pub(crate) fn assert_role(&self, role: Role) {
+        let account_id = env::predecessor_account_id();
+        require!(
+            account_id == self.owner_id || self.internal_has_role(role, &account_id),
+            "NOT_ALLOWED"
+        );
+    }
+
+    fn internal_has_role(&self, role: Role, account_id: &AccountId) -> bool {
+        self.roles
+            .get(&role)
+            .is_some_and(|members| members.contains(account_id))
+    }
+
+    fn internal_remove_role(&mut self, role: Role, account_id: &AccountId) {
+        if let Some(mut members) = self.roles.get(&role) {
+            if members.remove(account_id) {
+                self.roles.insert(&role, &members);
+                RoleRevoked { role, account_id }.emit();
+            }
+        }
+    }
+}
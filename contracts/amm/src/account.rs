@@ -0,0 +1,60 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::{env, AccountId, Balance, StorageUsage};
+
+use crate::StorageKey;
+
+/// Tokens an account keeps inside the AMM. Swaps and liquidity operations move these
+/// balances around; tokens only leave the contract through `withdraw`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Account {
+    /// NEAR attached through `storage_deposit`.
+    pub near_amount: Balance,
+    /// Bytes of deposit and share entries added on top of the account itself.
+    pub storage_used: StorageUsage,
+    pub tokens: UnorderedMap<AccountId, Balance>,
+}
+
+impl Account {
+    pub fn new(account_id: &AccountId) -> Self {
+        Self {
+            near_amount: 0,
+            storage_used: 0,
+            tokens: UnorderedMap::new(
+                StorageKey::AccountTokens {
+                    account_hash: get_account_hash(account_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            ),
+        }
+    }
+
+    pub fn get_balance(&self, token_id: &AccountId) -> Balance {
+        self.tokens.get(token_id).unwrap_or(0)
+    }
+
+    pub fn deposit(&mut self, token_id: &AccountId, amount: Balance) {
+        let balance = self
+            .get_balance(token_id)
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("BALANCE_OVERFLOW"));
+        self.tokens.insert(token_id, &balance);
+    }
+
+    pub fn withdraw(&mut self, token_id: &AccountId, amount: Balance) {
+        let balance = self
+            .get_balance(token_id)
+            .checked_sub(amount)
+            .unwrap_or_else(|| env::panic_str("NOT_ENOUGH_BALANCE"));
+        if balance == 0 {
+            self.tokens.remove(token_id);
+        } else {
+            self.tokens.insert(token_id, &balance);
+        }
+    }
+}
+
+pub fn get_account_hash(account_id: &AccountId) -> Vec<u8> {
+    env::sha256(account_id.as_bytes())
+}
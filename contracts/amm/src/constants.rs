@@ -1,5 +1,14 @@
 use near_sdk::Gas;
 
+pub(crate) const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+pub(crate) const GAS_FOR_FT_METADATA: Gas = Gas(5_000_000_000_000);
 pub(crate) const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
-pub(crate) const GAS_FOR_FT_TRANSFER_CALL: Gas =
-    Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
+pub(crate) const GAS_FOR_RESOLVE_POOL_DECIMALS: Gas = Gas(10_000_000_000_000);
+pub(crate) const GAS_FOR_FT_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+pub(crate) const GAS_FOR_MFT_ON_TRANSFER: Gas = Gas(25_000_000_000_000);
+pub(crate) const GAS_FOR_ON_FLASH_LOAN: Gas = Gas(100_000_000_000_000);
+pub(crate) const GAS_FOR_RESOLVE_FLASH_LOAN: Gas = Gas(30_000_000_000_000);
+
+/// Holder of the locked `MINIMUM_LIQUIDITY` shares. An implicit account nobody has the key to.
+pub(crate) const DEAD_ACCOUNT_ID: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000";
@@ -4,6 +4,9 @@ use near_sdk::AccountId;
 
 use near_sdk::env;
 
+use crate::access_control::Role;
+use crate::pause::Operation;
+
 #[derive(Serialize, Debug)]
 #[serde(tag = "standard")]
 #[must_use = "don't forget to `.emit()` this event"]
@@ -31,6 +34,7 @@ impl<'a> NearEvent<'a> {
 #[must_use]
 #[derive(Serialize, Debug, Clone)]
 pub struct LiquidityAdded<'a> {
+    pub pool_id: u64,
     pub account_id: &'a AccountId,
     pub shares: &'a U128,
     pub amount_0: &'a U128,
@@ -49,6 +53,7 @@ impl LiquidityAdded<'_> {
 #[must_use]
 #[derive(Serialize, Debug, Clone)]
 pub struct LiquidityRemoved<'a> {
+    pub pool_id: u64,
     pub account_id: &'a AccountId,
     pub shares: &'a U128,
     pub amount_0: &'a U128,
@@ -66,36 +71,335 @@ impl LiquidityRemoved<'_> {
 
 #[must_use]
 #[derive(Serialize, Debug, Clone)]
-pub struct LiquidityReceived<'a> {
+pub struct TokensSwaped<'a> {
+    pub pool_id: u64,
+    pub account_id: &'a AccountId,
+    pub token_in: &'a AccountId,
+    pub amount_out: &'a U128,
+    /// Human-normalized price of token_0 in token_1 after the swap, if the decimals are known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<U128>,
+}
+
+impl TokensSwaped<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[TokensSwaped<'_>]) {
+        new_141_v1(Nep141EventKind::TokensSwaped(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct PoolCreated<'a> {
+    pub pool_id: u64,
+    pub token_0: &'a AccountId,
+    pub token_1: &'a AccountId,
+}
+
+impl PoolCreated<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[PoolCreated<'_>]) {
+        new_141_v1(Nep141EventKind::PoolCreated(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct TokensDeposited<'a> {
     pub account_id: &'a AccountId,
     pub token_id: &'a AccountId,
     pub amount: &'a U128,
 }
 
-impl LiquidityReceived<'_> {
+impl TokensDeposited<'_> {
     pub fn emit(self) {
         Self::emit_many(&[self])
     }
-    pub fn emit_many(data: &[LiquidityReceived<'_>]) {
-        new_141_v1(Nep141EventKind::LiquidityReceived(data)).emit()
+    pub fn emit_many(data: &[TokensDeposited<'_>]) {
+        new_141_v1(Nep141EventKind::TokensDeposited(data)).emit()
     }
 }
 
 #[must_use]
 #[derive(Serialize, Debug, Clone)]
-pub struct TokensSwaped<'a> {
+pub struct TokensWithdrawn<'a> {
     pub account_id: &'a AccountId,
-    pub token_in: &'a AccountId,
-    pub amount_out: &'a U128,
+    pub token_id: &'a AccountId,
+    pub amount: &'a U128,
 }
 
-impl TokensSwaped<'_> {
+impl TokensWithdrawn<'_> {
     pub fn emit(self) {
         Self::emit_many(&[self])
     }
+    pub fn emit_many(data: &[TokensWithdrawn<'_>]) {
+        new_141_v1(Nep141EventKind::TokensWithdrawn(data)).emit()
+    }
+}
 
-    pub fn emit_many(data: &[TokensSwaped<'_>]) {
-        new_141_v1(Nep141EventKind::TokensSwaped(data)).emit()
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct PoolDecimalsSet {
+    pub pool_id: u64,
+    pub decimals_0: u8,
+    pub decimals_1: u8,
+}
+
+impl PoolDecimalsSet {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[PoolDecimalsSet]) {
+        new_141_v1(Nep141EventKind::PoolDecimalsSet(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct FeeConfigSet<'a> {
+    pub fee_bps: u32,
+    pub protocol_share_bps: u32,
+    pub fee_to: Option<&'a AccountId>,
+}
+
+impl FeeConfigSet<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[FeeConfigSet<'_>]) {
+        new_141_v1(Nep141EventKind::FeeConfigSet(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct OwnershipTransferred<'a> {
+    pub previous_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+}
+
+impl OwnershipTransferred<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[OwnershipTransferred<'_>]) {
+        new_141_v1(Nep141EventKind::OwnershipTransferred(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct RoleGranted<'a> {
+    pub role: Role,
+    pub account_id: &'a AccountId,
+}
+
+impl RoleGranted<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[RoleGranted<'_>]) {
+        new_141_v1(Nep141EventKind::RoleGranted(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct RoleRevoked<'a> {
+    pub role: Role,
+    pub account_id: &'a AccountId,
+}
+
+impl RoleRevoked<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[RoleRevoked<'_>]) {
+        new_141_v1(Nep141EventKind::RoleRevoked(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct Paused<'a> {
+    pub operations: &'a [Operation],
+}
+
+impl Paused<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[Paused<'_>]) {
+        new_141_v1(Nep141EventKind::Paused(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct Unpaused<'a> {
+    pub operations: &'a [Operation],
+}
+
+impl Unpaused<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[Unpaused<'_>]) {
+        new_141_v1(Nep141EventKind::Unpaused(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct ReservesSynced<'a> {
+    pub pool_id: u64,
+    pub reserve_0: &'a U128,
+    pub reserve_1: &'a U128,
+}
+
+impl ReservesSynced<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[ReservesSynced<'_>]) {
+        new_141_v1(Nep141EventKind::ReservesSynced(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct TokensSkimmed<'a> {
+    pub token_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl TokensSkimmed<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[TokensSkimmed<'_>]) {
+        new_141_v1(Nep141EventKind::TokensSkimmed(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct TransferFailed<'a> {
+    pub token_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl TransferFailed<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[TransferFailed<'_>]) {
+        new_141_v1(Nep141EventKind::TransferFailed(data)).emit()
+    }
+}
+
+/// Shares of `pool_id` minted to `owner_id`. Shaped like the NEP-141 `ft_mint` event, but
+/// named `mft_mint` so that indexers do not count the shares of every pool as one token.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MftMint<'a> {
+    pub pool_id: u64,
+    pub owner_id: &'a AccountId,
+    pub amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MftMint<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[MftMint<'_>]) {
+        new_141_v1(Nep141EventKind::MftMint(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MftBurn<'a> {
+    pub pool_id: u64,
+    pub owner_id: &'a AccountId,
+    pub amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MftBurn<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[MftBurn<'_>]) {
+        new_141_v1(Nep141EventKind::MftBurn(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MftTransfer<'a> {
+    pub pool_id: u64,
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MftTransfer<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[MftTransfer<'_>]) {
+        new_141_v1(Nep141EventKind::MftTransfer(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct FlashLoanSettled<'a> {
+    pub pool_id: u64,
+    pub account_id: &'a AccountId,
+    pub token_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub repaid_0: &'a U128,
+    pub repaid_1: &'a U128,
+    pub collateral_kept: bool,
+}
+
+impl FlashLoanSettled<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[FlashLoanSettled<'_>]) {
+        new_141_v1(Nep141EventKind::FlashLoanSettled(data)).emit()
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct ObservationCardinalityIncreased {
+    pub pool_id: u64,
+    pub cardinality_old: u64,
+    pub cardinality_new: u64,
+}
+
+impl ObservationCardinalityIncreased {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+    pub fn emit_many(data: &[ObservationCardinalityIncreased]) {
+        new_141_v1(Nep141EventKind::ObservationCardinalityIncreased(data)).emit()
     }
 }
 
@@ -114,7 +418,24 @@ enum Nep141EventKind<'a> {
     LiquidityAdded(&'a [LiquidityAdded<'a>]),
     TokensSwaped(&'a [TokensSwaped<'a>]),
     LiquidityRemoved(&'a [LiquidityRemoved<'a>]),
-    LiquidityReceived(&'a [LiquidityReceived<'a>]),
+    PoolCreated(&'a [PoolCreated<'a>]),
+    TokensDeposited(&'a [TokensDeposited<'a>]),
+    TokensWithdrawn(&'a [TokensWithdrawn<'a>]),
+    PoolDecimalsSet(&'a [PoolDecimalsSet]),
+    FeeConfigSet(&'a [FeeConfigSet<'a>]),
+    OwnershipTransferred(&'a [OwnershipTransferred<'a>]),
+    RoleGranted(&'a [RoleGranted<'a>]),
+    RoleRevoked(&'a [RoleRevoked<'a>]),
+    Paused(&'a [Paused<'a>]),
+    Unpaused(&'a [Unpaused<'a>]),
+    ReservesSynced(&'a [ReservesSynced<'a>]),
+    TokensSkimmed(&'a [TokensSkimmed<'a>]),
+    TransferFailed(&'a [TransferFailed<'a>]),
+    MftMint(&'a [MftMint<'a>]),
+    MftBurn(&'a [MftBurn<'a>]),
+    MftTransfer(&'a [MftTransfer<'a>]),
+    FlashLoanSettled(&'a [FlashLoanSettled<'a>]),
+    ObservationCardinalityIncreased(&'a [ObservationCardinalityIncreased]),
 }
 
 fn new_141<'a>(version: &'static str, event_kind: Nep141EventKind<'a>) -> NearEvent<'a> {
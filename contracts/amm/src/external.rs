@@ -1,10 +1,42 @@
-use crate::*;
 use near_sdk::json_types::U128;
 use near_sdk::{ext_contract, AccountId, PromiseOrValue};
 
+use crate::market_types::FungibleTokenMetadata;
+
 #[ext_contract(ext_ft_contract)]
 pub trait ExtFtContract {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
     fn ft_total_supply(&self) -> U128;
     fn ft_balance_of(&self, account_id: AccountId) -> PromiseOrValue<U128>;
+    fn ft_metadata(&self) -> FungibleTokenMetadata;
+}
+
+/// Implemented by contracts that accept pool shares through `mft_transfer_call`. Returns
+/// the amount of shares to hand back to the sender.
+#[ext_contract(ext_share_receiver)]
+pub trait ShareReceiver {
+    fn mft_on_transfer(
+        &mut self,
+        token_id: String,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+}
+
+/// Implemented by contracts that take flash loans. Called once the borrowed `amount` of
+/// `token_id` has arrived. Before the promise it returns resolves, the receiver has to
+/// send back `amount + fee` of it, or as much of the other token as a swap for the
+/// borrowed amount would take, through `ft_transfer_call` with a `flash_repay` message.
+#[ext_contract(ext_flash_loan_receiver)]
+pub trait FlashLoanReceiver {
+    fn on_flash_loan(
+        &mut self,
+        initiator_id: AccountId,
+        pool_id: u64,
+        token_id: AccountId,
+        amount: U128,
+        fee: U128,
+        msg: String,
+    ) -> PromiseOrValue<()>;
 }
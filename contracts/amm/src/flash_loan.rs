@@ -0,0 +1,231 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, near_bindgen, require, AccountId, Balance, Promise, PromiseOrValue,
+    PromiseResult,
+};
+
+use crate::access_control::Role;
+use crate::constants::{GAS_FOR_FT_TRANSFER, GAS_FOR_ON_FLASH_LOAN, GAS_FOR_RESOLVE_FLASH_LOAN};
+use crate::events::FlashLoanSettled;
+use crate::external::{ext_flash_loan_receiver, ext_ft_contract};
+use crate::math::{compute_flash_loan_fee, flash_loan_invariant_holds};
+use crate::pause::Operation;
+use crate::*;
+
+/// A flash loan in flight. Its pool stays locked until the loan is settled.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FlashLoan {
+    pub account_id: AccountId,
+    pub token_id: AccountId,
+    pub amount: Balance,
+    /// Principal plus fee, taken from the deposits of `account_id` and kept by the pool
+    /// if the loan is not paid back.
+    pub collateral: Balance,
+    /// Tokens sent back with `flash_repay` messages so far.
+    pub repaid_0: Balance,
+    pub repaid_1: Balance,
+}
+
+pub trait FlashLender {
+    fn flash_loan(
+        &mut self,
+        pool_id: u64,
+        token_id: AccountId,
+        amount: U128,
+        receiver_id: AccountId,
+        msg: String,
+    ) -> Promise;
+    #[allow(dead_code)]
+    fn resolve_flash_transfer(
+        &mut self,
+        pool_id: u64,
+        receiver_id: AccountId,
+        msg: String,
+    ) -> PromiseOrValue<()>;
+    #[allow(dead_code)]
+    fn resolve_flash_loan(&mut self, pool_id: u64);
+    fn unlock_pool(&mut self, pool_id: u64);
+}
+
+#[near_bindgen]
+impl FlashLender for Contract {
+    /// Lends `amount` of `token_id` out of the pool's reserves to `receiver_id` and calls
+    /// its `on_flash_loan`. NEAR cannot take back a transfer once it went out, so the
+    /// caller backs the loan with principal plus fee out of its own deposits. They are
+    /// returned when the repayments keep the pool's invariant and kept by the pool
+    /// otherwise. Nothing else can touch the pool's reserves until then.
+    #[payable]
+    fn flash_loan(
+        &mut self,
+        pool_id: u64,
+        token_id: AccountId,
+        amount: U128,
+        receiver_id: AccountId,
+        msg: String,
+    ) -> Promise {
+        assert_one_yocto();
+        self.assert_not_paused(Operation::Swap);
+        let account_id = env::predecessor_account_id();
+        let amount: Balance = amount.into();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_unlocked();
+        pool.assert_has_token(&token_id);
+        let reserve = if token_id == pool.token_0 {
+            pool.reserve_0
+        } else {
+            pool.reserve_1
+        };
+        require!(amount > 0, "AMOUNT_0");
+        require!(amount < reserve, "INSUFFICIENT_LIQUIDITY");
+
+        let collateral = amount + compute_flash_loan_fee(amount, self.fee_config.fee_bps);
+        require!(
+            self.internal_get_account(&account_id)
+                .get_balance(&token_id)
+                >= collateral,
+            "NOT_ENOUGH_BALANCE"
+        );
+        self.internal_withdraw(&account_id, &token_id, collateral);
+        pool.flash_loan = Some(FlashLoan {
+            account_id,
+            token_id: token_id.clone(),
+            amount,
+            collateral,
+            repaid_0: 0,
+            repaid_1: 0,
+        });
+        self.internal_save_pool(pool_id, &pool);
+        self.internal_start_transfer(&token_id, amount);
+
+        ext_ft_contract::ext(token_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                receiver_id.clone(),
+                amount.into(),
+                Some("flash loan".to_string()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_FLASH_LOAN + GAS_FOR_RESOLVE_FLASH_LOAN * 2)
+                    .resolve_flash_transfer(pool_id, receiver_id, msg),
+            )
+    }
+
+    /// Calls the receiver once the loan has arrived. A failed transfer settles the loan
+    /// right away, as nothing was lent.
+    #[private]
+    fn resolve_flash_transfer(
+        &mut self,
+        pool_id: u64,
+        receiver_id: AccountId,
+        msg: String,
+    ) -> PromiseOrValue<()> {
+        assert_eq!(env::promise_results_count(), 1, "Expected 1 promise result");
+
+        let mut pool = self.internal_get_pool(pool_id);
+        let loan = pool
+            .flash_loan
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NO_FLASH_LOAN"));
+        self.internal_finish_transfer(&loan.token_id, loan.amount);
+
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => ext_flash_loan_receiver::ext(receiver_id)
+                .with_static_gas(GAS_FOR_ON_FLASH_LOAN)
+                .on_flash_loan(
+                    loan.account_id.clone(),
+                    pool_id,
+                    loan.token_id.clone(),
+                    loan.amount.into(),
+                    (loan.collateral - loan.amount).into(),
+                    msg,
+                )
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_FLASH_LOAN)
+                        .resolve_flash_loan(pool_id),
+                )
+                .into(),
+            _ => {
+                let (token_id, amount) = (loan.token_id.clone(), loan.amount);
+                loan.amount = 0;
+                self.internal_save_pool(pool_id, &pool);
+                self.internal_increase_token_total(&token_id, amount);
+                self.internal_settle_flash_loan(pool_id);
+                PromiseOrValue::Value(())
+            }
+        }
+    }
+
+    /// Settles the loan after the receiver is done. Only what was repaid counts, not what
+    /// the receiver returned.
+    #[private]
+    fn resolve_flash_loan(&mut self, pool_id: u64) {
+        self.internal_settle_flash_loan(pool_id);
+    }
+
+    /// Settles the flash loan of a pool with what was repaid so far. Needs the `operator`
+    /// role. Meant for a loan whose `resolve_flash_loan` never ran to the end, which
+    /// would leave the pool locked otherwise.
+    #[payable]
+    fn unlock_pool(&mut self, pool_id: u64) {
+        assert_one_yocto();
+        self.assert_role(Role::Operator);
+        self.internal_settle_flash_loan(pool_id);
+    }
+}
+
+impl Contract {
+    /// Books the repayments into the reserves and unlocks the pool. The collateral goes
+    /// back to the borrower's deposits if the repayments keep the invariant, and into the
+    /// reserves otherwise.
+    fn internal_settle_flash_loan(&mut self, pool_id: u64) {
+        let mut pool = self.internal_get_pool(pool_id);
+        let loan = pool
+            .flash_loan
+            .take()
+            .unwrap_or_else(|| env::panic_str("NO_FLASH_LOAN"));
+        pool.record_observation();
+
+        let (borrowed_0, borrowed_1) = if loan.token_id == pool.token_0 {
+            (loan.amount, 0)
+        } else {
+            (0, loan.amount)
+        };
+        let is_repaid = flash_loan_invariant_holds(
+            pool.reserve_0,
+            pool.reserve_1,
+            borrowed_0,
+            borrowed_1,
+            loan.repaid_0,
+            loan.repaid_1,
+            self.fee_config.fee_bps,
+        );
+
+        let (mut repaid_0, mut repaid_1) = (loan.repaid_0, loan.repaid_1);
+        if is_repaid {
+            self.internal_deposit_unchecked(&loan.account_id, &loan.token_id, loan.collateral);
+        } else if loan.token_id == pool.token_0 {
+            repaid_0 += loan.collateral;
+        } else {
+            repaid_1 += loan.collateral;
+        }
+        pool.reserve_0 = pool.reserve_0 - borrowed_0 + repaid_0;
+        pool.reserve_1 = pool.reserve_1 - borrowed_1 + repaid_1;
+        self.internal_save_pool(pool_id, &pool);
+
+        FlashLoanSettled {
+            pool_id,
+            account_id: &loan.account_id,
+            token_id: &loan.token_id,
+            amount: &loan.amount.into(),
+            repaid_0: &loan.repaid_0.into(),
+            repaid_1: &loan.repaid_1.into(),
+            collateral_kept: !is_repaid,
+        }
+        .emit();
+    }
+}
@@ -1,19 +1,11 @@
-use near_sdk::{require, Balance, PromiseResult};
-
+use crate::events::TokensDeposited;
+use crate::market_types::{TransferAction, TransferMessage, TRANSFER_MESSAGE_VERSION};
+use crate::util::is_expired;
 use crate::*;
+use std::cmp;
 
-const ADD_LIQUIDITY: &str = "ADD_LIQUIDITY";
-const SWAP_TOKEN: &str = "SWAP_TOKEN";
-
-trait FungibleTokenReceiver {
+pub trait FungibleTokenReceiver {
     fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128;
-    fn process_received_liquidity(
-        &mut self,
-        transaction_sender_id: AccountId,
-        sender_id: AccountId,
-        amount: U128,
-    ) -> U128;
-    fn process_swap(&mut self, token_in: AccountId, sender_id: AccountId, _amount: U128) -> U128;
 }
 
 #[near_bindgen]
@@ -21,107 +13,294 @@ impl FungibleTokenReceiver for Contract {
     fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128 {
         let transaction_sender_id = env::predecessor_account_id();
 
-        require!(msg == ADD_LIQUIDITY || msg == SWAP_TOKEN, "INVALID_ACTION");
+        // Anything we cannot act on is handed back through the unused amount.
+        let message = match near_sdk::serde_json::from_str::<TransferMessage>(&msg) {
+            Ok(message) if message.version == TRANSFER_MESSAGE_VERSION => message,
+            _ => {
+                env::log_str("INVALID_ACTION");
+                return amount;
+            }
+        };
 
-        require!(
-            transaction_sender_id == self.token_0 || transaction_sender_id == self.token_1,
-            "INVALID_TOKEN"
-        );
+        let operations = message.action.operations();
+        if operations
+            .iter()
+            .any(|operation| self.paused.is_paused(*operation))
+        {
+            env::log_str("PAUSED");
+            return amount;
+        }
 
-        match msg.as_str() {
-            ADD_LIQUIDITY => {
-                self.process_received_liquidity(transaction_sender_id, sender_id, amount)
-            }
-            SWAP_TOKEN => self.process_swap(transaction_sender_id, sender_id, amount),
-            _ => env::panic_str("INVALID_ACTION"),
+        if is_expired(message.action.deadline()) {
+            env::log_str("EXPIRED");
+            return amount;
+        }
+
+        // Deposits take any token listed in a pool, pool actions only that pool's tokens.
+        let is_known_token = match message.action.pool_id() {
+            Some(pool_id) => self
+                .pools
+                .get(pool_id)
+                .is_some_and(|pool| pool.has_token(&transaction_sender_id)),
+            None => self.token_totals.contains_key(&transaction_sender_id),
         };
-        U128(0)
+        if !is_known_token {
+            env::log_str("INVALID_TOKEN");
+            return amount;
+        }
+
+        // A pool with a flash loan in flight only takes repayments.
+        let is_locked = match &message.action {
+            TransferAction::FlashRepay { .. } => false,
+            action => action
+                .pool_id()
+                .and_then(|pool_id| self.pools.get(pool_id))
+                .is_some_and(|pool| pool.is_locked()),
+        };
+        if is_locked {
+            env::log_str("POOL_LOCKED");
+            return amount;
+        }
+
+        if message
+            .action
+            .receiver_id(&sender_id)
+            .is_some_and(|receiver_id| !self.accounts.contains_key(receiver_id))
+        {
+            env::log_str("ACCOUNT_NOT_REGISTERED");
+            return amount;
+        }
+
+        let unused = self.internal_process_transfer_action(
+            transaction_sender_id.clone(),
+            sender_id,
+            amount,
+            message.action,
+        );
+        self.internal_increase_token_total(&transaction_sender_id, amount.0 - unused.0);
+        unused
     }
+}
 
-    fn process_received_liquidity(
+impl Contract {
+    /// Carries out `action` for `amount` of `token_id` sent by `sender_id`. Returns the
+    /// part of `amount` that goes back to the sender.
+    fn internal_process_transfer_action(
         &mut self,
         token_id: AccountId,
         sender_id: AccountId,
-        _amount: U128,
+        amount: U128,
+        action: TransferAction,
     ) -> U128 {
-        let _amount: Balance = _amount.into();
-
-        let liquidity_item = match self.received_liquidity_amount.get(&sender_id) {
-            Some(_liquidity_item) => {
-                let mut liquidity_item = _liquidity_item;
+        match action {
+            TransferAction::Deposit { beneficiary_id } => {
+                let beneficiary_id = beneficiary_id.unwrap_or(sender_id);
+                if !self.accounts.contains_key(&beneficiary_id) {
+                    env::log_str("ACCOUNT_NOT_REGISTERED");
+                    return amount;
+                }
+                self.internal_deposit_tokens(&beneficiary_id, &token_id, amount.0);
+                U128(0)
+            }
+            TransferAction::Swap {
+                pool_id,
+                min_amount_out,
+                receiver_id,
+                ..
+            } => {
+                let min_amount_out = min_amount_out.unwrap_or(U128(0)).0;
+                let amount_out = self.internal_get_amount_out(pool_id, &token_id, amount.0);
 
-                let mut received = liquidity_item.get(&token_id).unwrap_or_default();
+                // Nothing has moved yet, so the whole amount goes back to the sender.
+                if amount_out == 0 || amount_out < min_amount_out {
+                    env::log_str("INSUFFICIENT_OUTPUT_AMOUNT");
+                    return amount;
+                }
 
-                received.amount = U128(
-                    _amount
-                        .checked_add(received.amount.into())
-                        .unwrap_or_else(|| env::panic_str("PANICK_WHILE_UPDATING_SWAP_AMOUNT")),
+                let receiver_id = receiver_id.unwrap_or_else(|| sender_id.clone());
+                let amount_out =
+                    self.internal_swap(pool_id, &token_id, amount.0, min_amount_out, &sender_id);
+                let token_out = self.internal_get_pool(pool_id).other_token(&token_id);
+                self.internal_send_tokens(
+                    &token_out,
+                    &receiver_id,
+                    amount_out,
+                    "TRANSFER_SWAPPED_TOKEN",
                 );
-                received.received = true;
-                liquidity_item.insert(&token_id, &received);
-                liquidity_item
+                U128(0)
             }
-            None => {
-                let prefix = get_storage_prefix(sender_id.clone());
+            TransferAction::SwapExactOut {
+                pool_id,
+                amount_out,
+                receiver_id,
+                ..
+            } => {
+                let pool = self.internal_get_pool(pool_id);
+                let token_out = pool.other_token(&token_id);
+                let reserve_out = if token_out == pool.token_0 {
+                    pool.reserve_0
+                } else {
+                    pool.reserve_1
+                };
+                if amount_out.0 == 0 || amount_out.0 >= reserve_out {
+                    env::log_str("INSUFFICIENT_LIQUIDITY");
+                    return amount;
+                }
 
-                let mut liquidity_item: UnorderedMap<AccountId, LiquidityReceived> =
-                    UnorderedMap::new(prefix);
+                // Nothing has moved yet, so the whole amount goes back to the sender.
+                let amount_in = self.internal_get_amount_in(pool_id, &token_out, amount_out.0);
+                if amount_in > amount.0 {
+                    env::log_str("EXCESSIVE_INPUT_AMOUNT");
+                    return amount;
+                }
 
-                let received = LiquidityReceived {
-                    received: true,
-                    amount: _amount.into(),
-                };
-                liquidity_item.insert(&token_id, &received);
-                liquidity_item
+                let receiver_id = receiver_id.unwrap_or_else(|| sender_id.clone());
+                self.internal_swap_exact_out(
+                    pool_id,
+                    &token_out,
+                    amount_out.0,
+                    amount.0,
+                    &sender_id,
+                );
+                self.internal_send_tokens(
+                    &token_out,
+                    &receiver_id,
+                    amount_out.0,
+                    "TRANSFER_SWAPPED_TOKEN",
+                );
+                U128(amount.0 - amount_in)
             }
-        };
+            TransferAction::SwapRoute {
+                pool_ids,
+                min_amount_out,
+                receiver_id,
+                ..
+            } => {
+                let min_amount_out = min_amount_out.unwrap_or(U128(0)).0;
+                let (token_out, amount_out) =
+                    match self.internal_get_route_amount_out(&pool_ids, &token_id, amount.0) {
+                        Some(quote) => quote,
+                        None => {
+                            env::log_str("INVALID_ROUTE");
+                            return amount;
+                        }
+                    };
 
-        self.received_liquidity_amount
-            .insert(&sender_id, &liquidity_item);
+                if amount_out == 0 || amount_out < min_amount_out {
+                    env::log_str("INSUFFICIENT_OUTPUT_AMOUNT");
+                    return amount;
+                }
 
-        U128(0)
-    }
-
-    fn process_swap(&mut self, token_id: AccountId, sender_id: AccountId, _amount: U128) -> U128 {
-        let _amount: Balance = _amount.into();
-
-        let swap_item = match self.received_swap_amount.get(&sender_id) {
-            Some(_swap_item) => {
-                let mut swap_item = _swap_item;
-                let mut received = swap_item.get(&token_id).unwrap_or_default();
-                received.amount = U128(
-                    _amount
-                        .checked_add(received.amount.into())
-                        .unwrap_or_else(|| env::panic_str("PANICK_WHILE_UPDATING_SWAP_AMOUNT")),
+                let receiver_id = receiver_id.unwrap_or_else(|| sender_id.clone());
+                let amount_out =
+                    self.internal_swap_route(&pool_ids, &token_id, amount.0, &sender_id);
+                self.internal_send_tokens(
+                    &token_out,
+                    &receiver_id,
+                    amount_out,
+                    "TRANSFER_SWAPPED_TOKEN",
                 );
-                received.received = true;
-                swap_item.insert(&token_id, &received);
-                swap_item
+                U128(0)
             }
-            None => {
-                let prefix = get_storage_prefix(sender_id.clone());
-                let mut swap_item: UnorderedMap<AccountId, LiquidityReceived> =
-                    UnorderedMap::new(prefix);
-                let received = LiquidityReceived {
-                    received: true,
-                    amount: _amount.into(),
-                };
-                swap_item.insert(&token_id, &received);
-                swap_item
-            }
-        };
+            TransferAction::AddLiquidity {
+                pool_id,
+                min_shares,
+                max_amount_0,
+                max_amount_1,
+                beneficiary_id,
+                ..
+            } => {
+                let beneficiary_id = beneficiary_id.unwrap_or_else(|| sender_id.clone());
+                if !self.accounts.contains_key(&beneficiary_id) {
+                    env::log_str("ACCOUNT_NOT_REGISTERED");
+                    return amount;
+                }
+                self.internal_deposit_tokens(&beneficiary_id, &token_id, amount.0);
 
-        self.received_swap_amount.insert(&sender_id, &swap_item);
+                // Deposits of another account are only ever added to, never spent.
+                if beneficiary_id != sender_id {
+                    return U128(0);
+                }
 
-        U128(0)
+                // Of the transferred token, at most what came with this transfer is used.
+                let pool = self.internal_get_pool(pool_id);
+                let capped = |max_amount: Option<U128>| {
+                    Some(max_amount.map_or(amount.0, |max_amount| cmp::min(max_amount.0, amount.0)))
+                };
+                let (max_amount_0, max_amount_1) = if token_id == pool.token_0 {
+                    (
+                        capped(max_amount_0),
+                        max_amount_1.map(|max_amount_1| max_amount_1.0),
+                    )
+                } else {
+                    (
+                        max_amount_0.map(|max_amount_0| max_amount_0.0),
+                        capped(max_amount_1),
+                    )
+                };
+                let account = self.internal_get_account(&sender_id);
+                if account.get_balance(&pool.token_0) > 0 && account.get_balance(&pool.token_1) > 0
+                {
+                    self.internal_add_liquidity(
+                        pool_id,
+                        &sender_id,
+                        min_shares.unwrap_or(U128(0)).0,
+                        max_amount_0,
+                        max_amount_1,
+                    );
+                }
+                U128(0)
+            }
+            TransferAction::AddLiquidityOneToken {
+                pool_id,
+                min_shares,
+                beneficiary_id,
+                ..
+            } => {
+                let beneficiary_id = beneficiary_id.unwrap_or(sender_id);
+                if !self.accounts.contains_key(&beneficiary_id) {
+                    env::log_str("ACCOUNT_NOT_REGISTERED");
+                    return amount;
+                }
+                let (_, unused) = self.internal_add_liquidity_one_token(
+                    pool_id,
+                    &beneficiary_id,
+                    &token_id,
+                    amount.0,
+                    min_shares.unwrap_or(U128(0)).0,
+                );
+                U128(unused)
+            }
+            TransferAction::FlashRepay { pool_id } => {
+                let mut pool = self.internal_get_pool(pool_id);
+                let is_token_0 = token_id == pool.token_0;
+                match pool.flash_loan.as_mut() {
+                    Some(loan) if is_token_0 => loan.repaid_0 += amount.0,
+                    Some(loan) => loan.repaid_1 += amount.0,
+                    None => {
+                        env::log_str("NO_FLASH_LOAN");
+                        return amount;
+                    }
+                }
+                self.internal_save_pool(pool_id, &pool);
+                U128(0)
+            }
+        }
     }
-}
 
-pub fn get_storage_prefix(account_id: AccountId) -> Vec<u8> {
-    let prefix: Vec<u8> = [
-        b"s".as_slice(),
-        &near_sdk::env::sha256_array(account_id.as_bytes()),
-    ]
-    .concat();
-    prefix
+    /// Credits tokens that arrived with `ft_transfer_call` to `account_id`.
+    fn internal_deposit_tokens(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.internal_deposit(account_id, token_id, amount);
+        TokensDeposited {
+            account_id,
+            token_id,
+            amount: &amount.into(),
+        }
+        .emit();
+    }
 }
@@ -1,87 +1,1992 @@
-use std::default;
-
-use market_types::{LiquidityReceived, SwapReceived};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap};
+use near_sdk::collections::{LookupMap, UnorderedSet, Vector};
 use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, Balance, PanicOnDefault, StorageUsage};
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise,
+    StorageUsage,
+};
+
+use access_control::Role;
+use account::Account;
+use constants::{GAS_FOR_FT_METADATA, GAS_FOR_RESOLVE_POOL_DECIMALS};
+use events::{FeeConfigSet, ObservationCardinalityIncreased, PoolCreated};
+use external::ext_ft_contract;
+use market_types::FeeConfig;
+use pause::PauseFlags;
+use pool::{get_pair_key, Pool};
 
+pub mod access_control;
+pub mod account;
 pub mod constants;
 pub mod events;
 pub mod external;
+pub mod flash_loan;
 pub mod ft_receiver;
 pub mod market_core;
 pub mod market_inspect;
 pub mod market_types;
 pub mod market_writer;
+pub mod math;
+pub mod oracle;
+pub mod pause;
+pub mod pool;
+pub mod share_token;
+pub mod storage;
+pub mod sync;
 pub mod util;
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
-    pub reserve_0: Balance,
-    pub reserve_1: Balance,
-    pub total_supply: Balance,
-    pub token_0: AccountId,
-    pub token_1: AccountId,
-    pub balance: LookupMap<AccountId, Balance>,
-    pub received_liquidity_amount: LookupMap<AccountId, UnorderedMap<AccountId, LiquidityReceived>>,
-    pub received_swap_amount: LookupMap<AccountId, UnorderedMap<AccountId, LiquidityReceived>>,
+    pub owner_id: AccountId,
+    pub pending_owner_id: Option<AccountId>,
+    pub roles: LookupMap<Role, UnorderedSet<AccountId>>,
+    pub fee_config: FeeConfig,
+    pub paused: PauseFlags,
+    pub pools: Vector<Pool>,
+    pub pool_ids: LookupMap<String, u64>,
+    /// Amount of every token the contract owes to pool reserves and deposits.
+    pub token_totals: LookupMap<AccountId, Balance>,
+    /// Amount of every token sent out and no longer in `token_totals`, whose transfer
+    /// has not been resolved yet.
+    pub transfers_in_flight: LookupMap<AccountId, Balance>,
+    /// Tokens deposited by every account and not in a pool.
+    pub accounts: LookupMap<AccountId, Account>,
+    /// Size of a registered account, which its minimum storage balance pays for.
     pub bytes_for_longest_account_id: StorageUsage,
 }
 
 #[derive(BorshSerialize)]
 pub enum StorageKey {
-    Shares,
-    ReceivedLiquidityAmount,
-    ReceivedSwap,
+    Pools,
+    PoolIds,
+    Shares { pool_id: u64 },
+    Roles,
+    RoleMembers { role: Role },
+    TokenTotals,
+    Accounts,
+    AccountTokens { account_hash: Vec<u8> },
+    Observations { pool_id: u64 },
+    TransfersInFlight,
 }
 
 #[near_bindgen]
 impl Contract {
+    /// Starts the contract with the default 3% swap fee and no protocol fee unless
+    /// `fee_config` says otherwise.
     #[init]
-    pub fn init(owner_id: AccountId, _token_0: AccountId, _token_1: AccountId) -> Self {
-        let mut __self = Self {
-            reserve_0: 0_u128,
-            reserve_1: 0_u128,
-            token_0: _token_0,
-            token_1: _token_1,
-            total_supply: 0,
-            balance: LookupMap::new(StorageKey::Shares.try_to_vec().unwrap()),
-            bytes_for_longest_account_id: 0,
-            received_liquidity_amount: LookupMap::new(
-                StorageKey::ReceivedLiquidityAmount.try_to_vec().unwrap(),
+    pub fn init(owner_id: AccountId, fee_config: Option<FeeConfig>) -> Self {
+        let fee_config = fee_config.unwrap_or_default();
+        fee_config.assert_valid();
+        let mut this = Self {
+            owner_id,
+            pending_owner_id: None,
+            roles: LookupMap::new(StorageKey::Roles.try_to_vec().unwrap()),
+            fee_config,
+            paused: PauseFlags::default(),
+            pools: Vector::new(StorageKey::Pools.try_to_vec().unwrap()),
+            pool_ids: LookupMap::new(StorageKey::PoolIds.try_to_vec().unwrap()),
+            token_totals: LookupMap::new(StorageKey::TokenTotals.try_to_vec().unwrap()),
+            transfers_in_flight: LookupMap::new(
+                StorageKey::TransfersInFlight.try_to_vec().unwrap(),
             ),
-            received_swap_amount: LookupMap::new(StorageKey::ReceivedSwap.try_to_vec().unwrap()),
+            accounts: LookupMap::new(StorageKey::Accounts.try_to_vec().unwrap()),
+            bytes_for_longest_account_id: 0,
         };
-        __self
+        this.measure_bytes_for_longest_account_id();
+        this
+    }
+
+    /// Registers a new pool for the given pair of NEP-141 tokens and returns its id. The
+    /// attached deposit pays for the pool's storage, the rest is refunded. The decimals
+    /// of both tokens are fetched from their `ft_metadata` afterwards.
+    #[payable]
+    pub fn create_pool(&mut self, token_0: AccountId, token_1: AccountId) -> u64 {
+        require!(token_0 != token_1, "IDENTICAL_TOKENS");
+        let initial_storage = env::storage_usage();
+
+        let pair_key = get_pair_key(&token_0, &token_1);
+        require!(self.pool_ids.get(&pair_key).is_none(), "POOL_EXISTS");
+
+        let pool_id = self.pools.len();
+        self.pools
+            .push(&Pool::new(pool_id, token_0.clone(), token_1.clone()));
+        self.pool_ids.insert(&pair_key, &pool_id);
+        for token_id in [&token_0, &token_1] {
+            if !self.token_totals.contains_key(token_id) {
+                self.token_totals.insert(token_id, &0);
+            }
+        }
+
+        PoolCreated {
+            pool_id,
+            token_0: &token_0,
+            token_1: &token_1,
+        }
+        .emit();
+
+        self.internal_pay_storage_with_deposit(initial_storage);
+        self.internal_fetch_pool_decimals(pool_id, token_0, token_1);
+
+        pool_id
+    }
+
+    /// Fetches the decimals of both tokens of a pool again, e.g. after a token's
+    /// `ft_metadata` failed when the pool was created. Anyone can call it.
+    pub fn refresh_pool_decimals(&mut self, pool_id: u64) -> Promise {
+        let pool = self.internal_get_pool(pool_id);
+        self.internal_fetch_pool_decimals(pool_id, pool.token_0, pool.token_1)
+    }
+
+    /// Makes room for up to `cardinality` observations of a pool, so that `get_twap` can
+    /// reach back further. Like Uniswap v3's `increaseObservationCardinalityNext`, anyone
+    /// can call it and pays for the slots with the attached deposit, the rest is refunded.
+    #[payable]
+    pub fn increase_observation_cardinality(&mut self, pool_id: u64, cardinality: u64) {
+        let initial_storage = env::storage_usage();
+        let mut pool = self.internal_get_pool(pool_id);
+        let cardinality_old = pool.observations.len();
+        pool.increase_observation_cardinality(cardinality);
+        self.internal_save_pool(pool_id, &pool);
+
+        self.internal_pay_storage_with_deposit(initial_storage);
+        if pool.observations.len() > cardinality_old {
+            ObservationCardinalityIncreased {
+                pool_id,
+                cardinality_old,
+                cardinality_new: pool.observations.len(),
+            }
+            .emit();
+        }
+    }
+
+    /// Replaces the fee config. Needs the `fee_manager` role. Like Uniswap v2's `feeTo`, a
+    /// newly enabled protocol fee starts counting from the next liquidity change of a pool.
+    #[payable]
+    pub fn set_fee_config(&mut self, fee_config: FeeConfig) {
+        assert_one_yocto();
+        self.assert_role(Role::FeeManager);
+        fee_config.assert_valid();
+        self.fee_config = fee_config;
+
+        FeeConfigSet {
+            fee_bps: self.fee_config.fee_bps,
+            protocol_share_bps: self.fee_config.protocol_share_bps,
+            fee_to: self.fee_config.fee_to.as_ref(),
+        }
+        .emit();
+    }
+}
+
+impl Contract {
+    fn internal_fetch_pool_decimals(
+        &self,
+        pool_id: u64,
+        token_0: AccountId,
+        token_1: AccountId,
+    ) -> Promise {
+        ext_ft_contract::ext(token_0)
+            .with_static_gas(GAS_FOR_FT_METADATA)
+            .ft_metadata()
+            .and(
+                ext_ft_contract::ext(token_1)
+                    .with_static_gas(GAS_FOR_FT_METADATA)
+                    .ft_metadata(),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_POOL_DECIMALS)
+                    .resolve_pool_decimals(pool_id),
+            )
+    }
+
+    pub(crate) fn internal_get_pool(&self, pool_id: u64) -> Pool {
+        self.pools
+            .get(pool_id)
+            .unwrap_or_else(|| env::panic_str("INVALID_POOL"))
+    }
+
+    pub(crate) fn internal_save_pool(&mut self, pool_id: u64, pool: &Pool) {
+        self.pools.replace(pool_id, pool);
+    }
+
+    pub(crate) fn internal_get_account(&self, account_id: &AccountId) -> Account {
+        self.accounts
+            .get(account_id)
+            .unwrap_or_else(|| Account::new(account_id))
+    }
+
+    /// Credits `amount` of `token_id` to the deposits of `account_id`. A new entry is
+    /// charged to its storage balance.
+    pub(crate) fn internal_deposit(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let initial_storage = env::storage_usage();
+        let mut account = self.internal_get_registered_account(account_id);
+        account.deposit(token_id, amount);
+        self.accounts.insert(account_id, &account);
+        self.internal_charge_storage(account_id, initial_storage);
+    }
+
+    /// Like `internal_deposit`, but for callbacks, which must not fail: `account_id` is
+    /// registered if needed and the entry kept even when its storage balance falls short.
+    pub(crate) fn internal_deposit_unchecked(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        if !self.accounts.contains_key(account_id) {
+            self.accounts.insert(account_id, &Account::new(account_id));
+        }
+        let initial_storage = env::storage_usage();
+        let mut account = self.internal_get_account(account_id);
+        account.deposit(token_id, amount);
+        self.accounts.insert(account_id, &account);
+        self.internal_book_storage(account_id, initial_storage);
+    }
+
+    /// Debits `amount` of `token_id` from the deposits of `account_id`.
+    pub(crate) fn internal_withdraw(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let initial_storage = env::storage_usage();
+        let mut account = self.internal_get_registered_account(account_id);
+        account.withdraw(token_id, amount);
+        self.accounts.insert(account_id, &account);
+        self.internal_charge_storage(account_id, initial_storage);
+    }
+
+    pub(crate) fn internal_get_token_total(&self, token_id: &AccountId) -> Balance {
+        self.token_totals.get(token_id).unwrap_or(0)
+    }
+
+    /// Records tokens that came into the contract.
+    pub(crate) fn internal_increase_token_total(&mut self, token_id: &AccountId, amount: Balance) {
+        let total = self
+            .internal_get_token_total(token_id)
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("TOKEN_TOTAL_OVERFLOW"));
+        self.token_totals.insert(token_id, &total);
+    }
+
+    /// Records tokens that are sent out of the contract.
+    pub(crate) fn internal_decrease_token_total(&mut self, token_id: &AccountId, amount: Balance) {
+        let total = self
+            .internal_get_token_total(token_id)
+            .checked_sub(amount)
+            .unwrap_or_else(|| env::panic_str("TOKEN_TOTAL_UNDERFLOW"));
+        self.token_totals.insert(token_id, &total);
+    }
+
+    pub(crate) fn internal_get_in_flight(&self, token_id: &AccountId) -> Balance {
+        self.transfers_in_flight.get(token_id).unwrap_or(0)
+    }
+
+    /// Records tokens deducted from the token total whose transfer out is yet to resolve.
+    pub(crate) fn internal_start_transfer(&mut self, token_id: &AccountId, amount: Balance) {
+        self.internal_decrease_token_total(token_id, amount);
+        let in_flight = self.internal_get_in_flight(token_id) + amount;
+        self.transfers_in_flight.insert(token_id, &in_flight);
+    }
+
+    /// Records that a transfer out was resolved, one way or the other.
+    pub(crate) fn internal_finish_transfer(&mut self, token_id: &AccountId, amount: Balance) {
+        let in_flight = self.internal_get_in_flight(token_id) - amount;
+        if in_flight > 0 {
+            self.transfers_in_flight.insert(token_id, &in_flight);
+        } else {
+            self.transfers_in_flight.remove(token_id);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::access_control::AccessControl;
+    use crate::flash_loan::FlashLender;
+    use crate::ft_receiver::FungibleTokenReceiver;
+    use crate::market_core::MarketMakerCore;
+    use crate::market_inspect::MarketInspect;
+    use crate::market_writer::MarketWriter;
+    use crate::pause::{Operation, Pausable};
+    use crate::share_token::ShareToken;
+    use crate::storage::StorageManagement;
+    use crate::sync::ReserveSync;
+    use crate::util::to_yocto;
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    fn set_predecessor(account_id: &AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(account_id.clone())
+            .build());
+    }
+
+    fn set_promise_result(result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    fn register(contract: &mut Contract, account_id: &AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(account_id.clone())
+            .attached_deposit(to_yocto(1).0)
+            .build());
+        contract.storage_deposit(None, None);
+    }
+
+    fn create_pool(contract: &mut Contract, token_0: AccountId, token_1: AccountId) -> u64 {
+        testing_env!(VMContextBuilder::new()
+            .attached_deposit(to_yocto(1).0)
+            .build());
+        contract.create_pool(token_0, token_1)
+    }
+
+    /// Pauses swaps and liquidity additions, as the owner `alice.testnet`.
+    fn pause_pools(contract: &mut Contract) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("alice.testnet".parse().unwrap())
+            .attached_deposit(1)
+            .build());
+        contract.pause(vec![Operation::Swap, Operation::AddLiquidity]);
+    }
+
+    fn set_reserves(contract: &mut Contract, pool_id: u64, reserve_0: u128, reserve_1: u128) {
+        let mut pool = contract.internal_get_pool(pool_id);
+        contract.internal_decrease_token_total(&pool.token_0, pool.reserve_0);
+        contract.internal_decrease_token_total(&pool.token_1, pool.reserve_1);
+        contract.internal_increase_token_total(&pool.token_0, reserve_0);
+        contract.internal_increase_token_total(&pool.token_1, reserve_1);
+        pool.reserve_0 = reserve_0;
+        pool.reserve_1 = reserve_1;
+        contract.internal_save_pool(pool_id, &pool);
+    }
+
     #[test]
     fn test_init() {
         let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let contract = Contract::init(owner_id, None);
+        assert_eq!(contract.get_number_of_pools(), 0);
+        assert_eq!(contract.get_fee_config(), FeeConfig::default());
+    }
+
+    #[test]
+    fn test_set_fee_config() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(owner_id.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+        let amount_out = contract.internal_get_amount_out(pool_id, &token_0, to_yocto(10).0);
+
+        let fee_config = FeeConfig {
+            fee_bps: 30,
+            protocol_share_bps: 1_666,
+            fee_to: Some(owner_id.clone()),
+        };
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(owner_id)
+            .attached_deposit(1)
+            .build());
+        contract.set_fee_config(fee_config.clone());
+
+        assert_eq!(contract.get_fee_config(), fee_config);
+        assert!(contract.internal_get_amount_out(pool_id, &token_0, to_yocto(10).0) > amount_out);
+    }
+
+    #[test]
+    #[should_panic(expected = "NOT_ALLOWED")]
+    fn test_set_fee_config_not_allowed() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = Contract::init(owner_id, None);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .attached_deposit(1)
+            .build());
+        contract.set_fee_config(FeeConfig::default());
+    }
+
+    #[test]
+    fn test_two_step_ownership_transfer() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = Contract::init(owner_id.clone(), None);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(owner_id)
+            .attached_deposit(1)
+            .build());
+        contract.propose_owner(Some(alice.clone()));
+        assert_eq!(contract.get_pending_owner(), Some(alice.clone()));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.accept_owner();
+        assert_eq!(contract.get_owner(), alice);
+        assert_eq!(contract.get_pending_owner(), None);
+    }
+
+    #[test]
+    fn test_fee_manager_role() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = Contract::init(owner_id.clone(), None);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(owner_id)
+            .attached_deposit(1)
+            .build());
+        contract.grant_role(Role::FeeManager, alice.clone());
+        assert!(contract.has_role(Role::FeeManager, alice.clone()));
+        assert!(!contract.has_role(Role::Pauser, alice.clone()));
+        assert_eq!(
+            contract.get_role_members(Role::FeeManager),
+            vec![alice.clone()]
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        let fee_config = FeeConfig {
+            fee_bps: 30,
+            ..FeeConfig::default()
+        };
+        contract.set_fee_config(fee_config.clone());
+        assert_eq!(contract.get_fee_config(), fee_config);
+
+        contract.renounce_role(Role::FeeManager);
+        assert!(!contract.has_role(Role::FeeManager, alice));
+    }
+
+    #[test]
+    fn test_protocol_fee_minted_as_shares() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(
+            alice.clone(),
+            Some(FeeConfig {
+                fee_bps: 300,
+                protocol_share_bps: 10_000,
+                fee_to: Some(carol.clone()),
+            }),
+        );
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        contract.mint(pool_id, bob, to_yocto(100).0);
+        contract.internal_set_root_k_last(pool_id, to_yocto(100).0, to_yocto(100).0);
+
+        // Swap fees grew the pool; with the whole fee going to the protocol, carol is owed
+        // exactly the growth.
+        set_reserves(&mut contract, pool_id, to_yocto(121).0, to_yocto(121).0);
+        assert_eq!(contract.get_protocol_fees(pool_id), to_yocto(21));
+        assert_eq!(contract.get_balance_of(pool_id, carol.clone()), U128(0));
+
+        for token_id in [&token_0, &token_1] {
+            set_predecessor(token_id);
+            contract.ft_on_transfer(
+                alice.clone(),
+                to_yocto(121),
+                r#"{"action":"deposit"}"#.to_string(),
+            );
+        }
+        set_predecessor(&alice);
+        let shares = contract.add_liquidity(pool_id, None, None, None, None);
+
+        assert_eq!(shares, to_yocto(121));
+        assert_eq!(contract.get_balance_of(pool_id, carol), to_yocto(21));
+        assert_eq!(contract.get_protocol_fees(pool_id), to_yocto(21));
+    }
+
+    #[test]
+    fn test_create_pool() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(owner_id, None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        assert_eq!(pool_id, 0);
+        assert_eq!(contract.get_token_0(pool_id), token_0);
+        assert_eq!(contract.get_token_1(pool_id), token_1);
+        assert_eq!(contract.get_reserve_0(pool_id), U128(0));
+        assert_eq!(contract.get_reserve_1(pool_id), U128(0));
+        assert_eq!(contract.get_total_supply(pool_id), U128(0));
+        assert_eq!(contract.get_pool_id(token_1, token_0), Some(pool_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "POOL_EXISTS")]
+    fn test_create_duplicate_pool() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(owner_id, None);
+        create_pool(&mut contract, token_0.clone(), token_1.clone());
+        create_pool(&mut contract, token_1, token_0);
+    }
+
+    #[test]
+    fn test_storage_registration() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        create_pool(&mut contract, token_0.clone(), token_1);
+
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        assert_eq!(unused, to_yocto(10));
+
+        register(&mut contract, &alice);
+        let min_balance = contract.storage_balance_bounds().min.0;
+        assert!(min_balance > 0);
+        let available = to_yocto(1).0 - min_balance;
+        assert_eq!(
+            contract
+                .storage_balance_of(alice.clone())
+                .unwrap()
+                .available,
+            U128(available)
+        );
+
+        // The deposit entry is paid for out of alice's storage balance...
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        assert!(
+            contract
+                .storage_balance_of(alice.clone())
+                .unwrap()
+                .available
+                .0
+                < available
+        );
+
+        // ...and released again once it is withdrawn.
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(token_0, None);
+        assert_eq!(
+            contract
+                .storage_balance_of(alice.clone())
+                .unwrap()
+                .available,
+            U128(available)
+        );
+        assert!(contract.storage_unregister(None));
+        assert_eq!(contract.storage_balance_of(alice), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "INSUFFICIENT_STORAGE")]
+    fn test_deposit_beyond_storage_balance() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        create_pool(&mut contract, token_0.clone(), token_1);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(to_yocto(1).0)
+            .build());
+        contract.storage_deposit(None, Some(true));
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(alice, to_yocto(10), r#"{"action":"deposit"}"#.to_string());
+    }
+
+    #[test]
+    fn test_swap_from_deposits() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(20),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        set_predecessor(&alice);
+        let amount_out = contract.swap(pool_id, token_0.clone(), to_yocto(10), None, None);
+        assert!(amount_out.0 > 0);
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_0.clone()),
+            to_yocto(10)
+        );
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_1.clone()),
+            amount_out
+        );
+        assert_eq!(contract.get_deposits(alice).len(), 2);
+        // Nothing left the contract.
+        assert_eq!(contract.get_token_total(token_1), to_yocto(1_000));
+    }
+
+    #[test]
+    fn test_quotes_match_swap() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(3_000).0);
+
+        // The least input for an output is exact: one unit less falls short.
+        let amount_out = to_yocto(7).0 + 123;
+        let amount_in = contract
+            .get_amount_in(pool_id, token_1.clone(), U128(amount_out))
+            .0;
+        assert!(
+            contract
+                .get_amount_out(pool_id, token_0.clone(), U128(amount_in))
+                .0
+                >= amount_out
+        );
+        assert!(
+            contract
+                .get_amount_out(pool_id, token_0.clone(), U128(amount_in - 1))
+                .0
+                < amount_out
+        );
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        let quote = contract.get_amount_out(pool_id, token_0.clone(), to_yocto(10));
+        set_predecessor(&alice);
+        assert_eq!(
+            contract.swap(pool_id, token_0, to_yocto(10), None, None),
+            quote
+        );
+    }
+
+    #[test]
+    fn test_swap_exact_out() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(20),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        let amount_in = contract.get_amount_in(pool_id, token_1.clone(), to_yocto(10));
+        set_predecessor(&alice);
+        let spent =
+            contract.swap_exact_out(pool_id, token_1.clone(), to_yocto(10), to_yocto(11), None);
+        assert_eq!(spent, amount_in);
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_1.clone()),
+            to_yocto(10)
+        );
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_0.clone()),
+            U128(to_yocto(20).0 - spent.0)
+        );
+
+        // In one transfer, whatever is not spent goes back to the sender.
+        let amount_in = contract.get_amount_in(pool_id, token_1, to_yocto(10));
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(20),
+            r#"{"action":"swap_exact_out","pool_id":0,"amount_out":"10000000000000000000000000"}"#
+                .to_string(),
+        );
+        assert_eq!(unused, U128(to_yocto(20).0 - amount_in.0));
+
+        let unused = contract.ft_on_transfer(
+            alice,
+            to_yocto(5),
+            r#"{"action":"swap_exact_out","pool_id":0,"amount_out":"10000000000000000000000000"}"#
+                .to_string(),
+        );
+        assert_eq!(unused, to_yocto(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "INSUFFICIENT_OUTPUT_AMOUNT")]
+    fn test_swap_below_min_amount_out() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        set_predecessor(&alice);
+        contract.swap(pool_id, token_0, to_yocto(10), Some(to_yocto(10)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "INSUFFICIENT_OUTPUT_AMOUNT")]
+    fn test_swap_into_empty_pool() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        set_predecessor(&alice);
+        contract.swap(pool_id, token_0, to_yocto(10), None, None);
+    }
+
+    #[test]
+    fn test_expired_action_is_refunded() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(token_0.clone())
+            .block_timestamp(2_000)
+            .build());
+        for msg in [
+            r#"{"action":"swap","pool_id":0,"deadline":"1999"}"#,
+            r#"{"action":"add_liquidity","pool_id":0,"deadline":"1999"}"#,
+        ] {
+            let unused = contract.ft_on_transfer(alice.clone(), to_yocto(10), msg.to_string());
+            assert_eq!(unused, to_yocto(10));
+        }
+
+        let unused = contract.ft_on_transfer(
+            alice,
+            to_yocto(10),
+            r#"{"action":"swap","pool_id":0,"deadline":"2000"}"#.to_string(),
+        );
+        assert_eq!(unused, U128(0));
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(1_010));
+    }
+
+    #[test]
+    #[should_panic(expected = "EXPIRED")]
+    fn test_swap_after_deadline() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .block_timestamp(2_000)
+            .build());
+        contract.swap(pool_id, token_0, to_yocto(10), None, Some(U64(1_999)));
+    }
+
+    #[test]
+    fn test_invalid_message_is_refunded() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        create_pool(&mut contract, token_0.clone(), token_1);
+
+        set_predecessor(&token_0);
+        for msg in [
+            "ADD_LIQUIDITY",
+            r#"{"action":"borrow","pool_id":0}"#,
+            r#"{"version":2,"action":"deposit"}"#,
+            r#"{"action":"deposit_swap","pool_id":0}"#,
+            r#"{"action":"swap","pool_id":1}"#,
+        ] {
+            let unused = contract.ft_on_transfer(alice.clone(), to_yocto(10), msg.to_string());
+            assert_eq!(unused, to_yocto(10));
+        }
+
+        // Tokens that are not listed in any pool cannot be deposited.
+        set_predecessor(&"token_3.testnet".parse().unwrap());
+        let unused =
+            contract.ft_on_transfer(alice, to_yocto(10), r#"{"action":"deposit"}"#.to_string());
+        assert_eq!(unused, to_yocto(10));
+    }
+
+    #[test]
+    fn test_deposit_for_beneficiary() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &bob);
+
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"version":1,"action":"deposit","beneficiary_id":"bob.testnet"}"#.to_string(),
+        );
+        assert_eq!(unused, U128(0));
+        assert_eq!(contract.get_deposit(bob, token_0.clone()), to_yocto(10));
+        assert_eq!(contract.get_deposit(alice, token_0), U128(0));
+    }
+
+    #[test]
+    fn test_atomic_swap() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"swap","pool_id":0,"min_amount_out":"8000000000000000000000000"}"#
+                .to_string(),
+        );
+        assert_eq!(unused, U128(0));
+
+        let unused = contract.ft_on_transfer(
+            alice,
+            to_yocto(10),
+            r#"{"action":"swap","pool_id":0,"min_amount_out":"10000000000000000000000000"}"#
+                .to_string(),
+        );
+        assert_eq!(unused, to_yocto(10));
+    }
+
+    #[test]
+    fn test_swap_to_unregistered_receiver() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        for msg in [
+            r#"{"action":"swap","pool_id":0,"receiver_id":"bob.testnet"}"#,
+            r#"{"action":"swap_exact_out","pool_id":0,"amount_out":"1","receiver_id":"bob.testnet"}"#,
+            r#"{"action":"swap_route","pool_ids":[0],"receiver_id":"bob.testnet"}"#,
+        ] {
+            let unused = contract.ft_on_transfer(alice.clone(), to_yocto(10), msg.to_string());
+            assert_eq!(unused, to_yocto(10));
+        }
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(1_000));
+        assert!(contract
+            .accounts
+            .get(&"bob.testnet".parse().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn test_swap_route() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_a: AccountId = "token_a.testnet".parse().unwrap();
+        let token_b: AccountId = "token_b.testnet".parse().unwrap();
+        let token_c: AccountId = "token_c.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_ab = create_pool(&mut contract, token_a.clone(), token_b.clone());
+        let pool_bc = create_pool(&mut contract, token_b.clone(), token_c.clone());
+        set_reserves(&mut contract, pool_ab, to_yocto(1_000).0, to_yocto(1_000).0);
+        set_reserves(&mut contract, pool_bc, to_yocto(1_000).0, to_yocto(1_000).0);
+        register(&mut contract, &alice);
+
+        let amount_b =
+            math::get_amount_out(to_yocto(10).0, to_yocto(1_000).0, to_yocto(1_000).0, 300);
+        let amount_c = math::get_amount_out(amount_b, to_yocto(1_000).0, to_yocto(1_000).0, 300);
+
+        set_predecessor(&token_a);
+        for msg in [
+            r#"{"action":"swap_route","pool_ids":[0,0]}"#,
+            r#"{"action":"swap_route","pool_ids":[1]}"#,
+            r#"{"action":"swap_route","pool_ids":[0,1],"min_amount_out":"9300000000000000000000000"}"#,
+        ] {
+            let unused = contract.ft_on_transfer(alice.clone(), to_yocto(10), msg.to_string());
+            assert_eq!(unused, to_yocto(10));
+        }
+
+        let unused = contract.ft_on_transfer(
+            alice,
+            to_yocto(10),
+            r#"{"action":"swap_route","pool_ids":[0,1],"min_amount_out":"9200000000000000000000000"}"#
+                .to_string(),
+        );
+        assert_eq!(unused, U128(0));
+        assert_eq!(contract.get_reserve_0(pool_ab), to_yocto(1_010));
+        assert_eq!(
+            contract.get_reserve_1(pool_ab),
+            U128(to_yocto(1_000).0 - amount_b)
+        );
+        assert_eq!(
+            contract.get_reserve_0(pool_bc),
+            U128(to_yocto(1_000).0 + amount_b)
+        );
+        assert_eq!(
+            contract.get_reserve_1(pool_bc),
+            U128(to_yocto(1_000).0 - amount_c)
+        );
+        // Only token_c left the contract.
+        assert_eq!(contract.get_token_total(token_b), to_yocto(2_000));
+        assert_eq!(
+            contract.get_token_total(token_c),
+            U128(to_yocto(1_000).0 - amount_c)
+        );
+    }
+
+    #[test]
+    fn test_reserves_tracked_synchronously() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+
+        for token_id in [&token_0, &token_1] {
+            set_predecessor(token_id);
+            contract.ft_on_transfer(
+                alice.clone(),
+                to_yocto(100),
+                r#"{"action":"add_liquidity","pool_id":0}"#.to_string(),
+            );
+        }
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(5),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(100));
+        assert_eq!(contract.get_reserve_1(pool_id), to_yocto(100));
+        // Deposits are owed, but not part of the reserves.
+        assert_eq!(contract.get_token_total(token_0.clone()), to_yocto(105));
+
+        contract.ft_on_transfer(
+            alice,
+            to_yocto(10),
+            r#"{"action":"swap","pool_id":0}"#.to_string(),
+        );
+        let amount_out =
+            math::get_amount_out(to_yocto(10).0, to_yocto(100).0, to_yocto(100).0, 300);
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(110));
+        assert_eq!(
+            contract.get_reserve_1(pool_id),
+            U128(to_yocto(100).0 - amount_out)
+        );
+        assert_eq!(contract.get_token_total(token_0), to_yocto(115));
+        assert_eq!(
+            contract.get_token_total(token_1),
+            U128(to_yocto(100).0 - amount_out)
+        );
+    }
+
+    #[test]
+    fn test_sync_adds_untracked_balance() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice, None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(100).0);
+
+        pause_pools(&mut contract);
+        // 7 token_0 were sent with a plain ft_transfer.
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(br#""107000000000000000000000000""#.to_vec()),
+                PromiseResult::Successful(br#""100000000000000000000000000""#.to_vec()),
+            ],
+        );
+        contract.resolve_sync(pool_id);
+
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(107));
+        assert_eq!(contract.get_reserve_1(pool_id), to_yocto(100));
+        assert_eq!(contract.get_token_total(token_0), to_yocto(107));
+    }
+
+    #[test]
+    #[should_panic(expected = "NOT_PAUSED")]
+    fn test_sync_not_paused() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0, token_1);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .attached_deposit(1)
+            .build());
+        contract.sync(pool_id);
+    }
+
+    #[test]
+    fn test_sync_skips_transfers_in_flight() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(100).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(token_0.clone(), None);
+
+        pause_pools(&mut contract);
+        // The token still holds the 10 that are on their way to alice.
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(br#""110000000000000000000000000""#.to_vec()),
+                PromiseResult::Successful(br#""100000000000000000000000000""#.to_vec()),
+            ],
+        );
+        contract.resolve_sync(pool_id);
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(100));
+
+        set_promise_result(PromiseResult::Successful(vec![]));
+        contract.resolve_transfer(token_0.clone(), alice, to_yocto(10));
+        assert_eq!(contract.internal_get_in_flight(&token_0), 0);
+        assert_eq!(contract.get_token_total(token_0), to_yocto(100));
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(token_0.clone(), Some(to_yocto(4)));
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_0.clone()),
+            to_yocto(6)
+        );
+        assert_eq!(contract.get_token_total(token_0.clone()), to_yocto(6));
+
+        // alice is not registered on token_0, so the transfer bounces.
+        set_promise_result(PromiseResult::Failed);
+        contract.resolve_transfer(token_0.clone(), alice.clone(), to_yocto(4));
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_0.clone()),
+            to_yocto(10)
+        );
+        assert_eq!(contract.get_token_total(token_0.clone()), to_yocto(10));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(token_0.clone(), None);
+        assert_eq!(contract.get_deposit(alice, token_0.clone()), U128(0));
+        assert_eq!(contract.get_token_total(token_0), U128(0));
+    }
+
+    #[test]
+    fn test_pause_swaps_keeps_withdrawals_open() {
+        let owner_id: AccountId = "ykel.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
         let token_0: AccountId = "token_1.testnet".parse().unwrap();
         let token_1: AccountId = "token_2.testnet".parse().unwrap();
-        let contract = Contract::init(owner_id, token_0.clone(), token_1.clone());
-        assert_eq!(contract.token_0, token_0);
-        assert_eq!(contract.reserve_0, 0);
-        assert_eq!(contract.reserve_1, 0);
-        assert_eq!(contract.total_supply, 0);
+        let mut contract = Contract::init(owner_id.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(owner_id)
+            .attached_deposit(1)
+            .build());
+        contract.pause(vec![Operation::Swap]);
+        assert!(contract.is_paused(Operation::Swap));
+        assert!(!contract.is_paused(Operation::AddLiquidity));
+
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"swap","pool_id":0}"#.to_string(),
+        );
+        assert_eq!(unused, to_yocto(10));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(token_0.clone(), Some(to_yocto(10)));
+        assert_eq!(contract.get_deposit(alice, token_0), U128(0));
     }
 
     #[test]
-    fn test_add_deposit() {
+    #[should_panic(expected = "PAUSED")]
+    fn test_swap_while_paused() {
         let owner_id: AccountId = "ykel.testnet".parse().unwrap();
         let token_0: AccountId = "token_1.testnet".parse().unwrap();
         let token_1: AccountId = "token_2.testnet".parse().unwrap();
-        let contract = Contract::init(owner_id, token_0.clone(), token_1.clone());
-        assert_eq!(contract.token_0, token_0);
-        assert_eq!(contract.reserve_0, 0);
-        assert_eq!(contract.reserve_1, 0);
-        assert_eq!(contract.total_supply, 0);
+        let mut contract = Contract::init(owner_id.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(owner_id)
+            .attached_deposit(1)
+            .build());
+        contract.pause(vec![Operation::Swap]);
+        contract.swap(pool_id, token_0, to_yocto(1), None, None);
+    }
+
+    #[test]
+    fn test_remove_liquidity_credits_deposits() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        contract.mint(pool_id, alice.clone(), to_yocto(100).0);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(400).0);
+
+        set_predecessor(&alice);
+        contract.remove_liquidity(pool_id, to_yocto(10));
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_0.clone()),
+            to_yocto(10)
+        );
+        assert_eq!(contract.get_deposit(alice, token_1), to_yocto(40));
+        assert_eq!(contract.get_reserve_0(pool_id), to_yocto(90));
+        assert_eq!(contract.get_token_total(token_0), to_yocto(100));
+    }
+
+    #[test]
+    fn test_transfer_shares() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let farm: AccountId = "farm.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0, token_1);
+        register(&mut contract, &alice);
+        register(&mut contract, &farm);
+        contract.mint(pool_id, alice.clone(), to_yocto(100).0);
+        assert_eq!(contract.mft_metadata(":0".to_string()).symbol, "LP-0");
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.mft_transfer_call(
+            ":0".to_string(),
+            farm.clone(),
+            to_yocto(40),
+            None,
+            "stake".to_string(),
+        );
+        assert_eq!(
+            contract.mft_balance_of(":0".to_string(), alice.clone()),
+            to_yocto(60)
+        );
+        assert_eq!(
+            contract.mft_balance_of(":0".to_string(), farm.clone()),
+            to_yocto(40)
+        );
+
+        // The farm only took 30 of them.
+        set_promise_result(PromiseResult::Successful(
+            br#""10000000000000000000000000""#.to_vec(),
+        ));
+        let used = contract.mft_resolve_transfer(
+            ":0".to_string(),
+            alice.clone(),
+            farm.clone(),
+            to_yocto(40),
+        );
+        assert_eq!(used, to_yocto(30));
+        assert_eq!(
+            contract.mft_balance_of(":0".to_string(), alice),
+            to_yocto(70)
+        );
+        assert_eq!(
+            contract.mft_balance_of(":0".to_string(), farm),
+            to_yocto(30)
+        );
+        assert_eq!(contract.mft_total_supply(":0".to_string()), to_yocto(100));
+    }
+
+    #[test]
+    fn test_first_deposit_donation_attack_not_profitable() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        register(&mut contract, &bob);
+
+        // alice seeds the pool with dust and keeps a single share.
+        let seed = math::MINIMUM_LIQUIDITY + 1;
+        for token_id in [&token_0, &token_1] {
+            set_predecessor(token_id);
+            contract.ft_on_transfer(
+                alice.clone(),
+                U128(seed),
+                r#"{"action":"add_liquidity","pool_id":0}"#.to_string(),
+            );
+        }
+        assert_eq!(contract.get_balance_of(pool_id, alice.clone()), U128(1));
+        assert_eq!(
+            contract.get_balance_of(pool_id, constants::DEAD_ACCOUNT_ID.parse().unwrap()),
+            U128(math::MINIMUM_LIQUIDITY)
+        );
+
+        // She then donates 10 of each token to inflate the price of a share...
+        let donation = to_yocto(10).0;
+        set_reserves(&mut contract, pool_id, seed + donation, seed + donation);
+
+        // ...hoping that bob's deposit is rounded down in her favour.
+        for token_id in [&token_0, &token_1] {
+            set_predecessor(token_id);
+            contract.ft_on_transfer(
+                bob.clone(),
+                to_yocto(15),
+                r#"{"action":"add_liquidity","pool_id":0}"#.to_string(),
+            );
+        }
+
+        set_predecessor(&alice);
+        contract.remove_liquidity(pool_id, U128(1));
+        let alice_out = contract.get_deposit(alice, token_0.clone()).0;
+        assert!(alice_out < (seed + donation) / 100);
+
+        set_predecessor(&bob);
+        let bob_shares = contract.get_balance_of(pool_id, bob.clone());
+        contract.remove_liquidity(pool_id, bob_shares);
+        let bob_out = contract.get_deposit(bob, token_0).0;
+        assert!(bob_out > to_yocto(15).0 / 1_000 * 999);
+    }
+
+    #[test]
+    fn test_add_liquidity_keeps_excess_deposited() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        contract.mint(pool_id, bob, to_yocto(100).0);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(200).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        set_predecessor(&token_1);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(50),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        set_predecessor(&alice);
+        let shares = contract.add_liquidity(pool_id, Some(to_yocto(10)), None, None, None);
+        assert_eq!(shares, to_yocto(10));
+        assert_eq!(contract.get_deposit(alice.clone(), token_0), U128(0));
+        assert_eq!(contract.get_deposit(alice, token_1), to_yocto(30));
+    }
+
+    #[test]
+    #[should_panic(expected = "INSUFFICIENT_SHARES")]
+    fn test_add_liquidity_below_min_shares() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        contract.mint(pool_id, bob, to_yocto(100).0);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(200).0);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        set_predecessor(&token_1);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(50),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        set_predecessor(&alice);
+        contract.add_liquidity(pool_id, Some(to_yocto(10)), Some(to_yocto(5)), None, None);
+    }
+
+    #[test]
+    fn test_math_keeps_base_unit_precision() {
+        // 6-decimal token amounts, all well below one 24-decimal unit.
+        assert_eq!(
+            math::compute_initial_shares(1_000_000, 4_000_000),
+            2_000_000
+        );
+        assert_eq!(
+            math::compute_shares(1_000_000, 2_000_000, 10_000_000, 20_000_000, 14_142_135),
+            1_414_213
+        );
+        assert_eq!(
+            math::get_amount_out(1_000_000, 1_000_000_000_000, 1_000_000_000_000, 300),
+            969_999
+        );
+        assert_eq!(
+            math::compute_withdraw(1_414_213, 10_000_000, 14_142_135),
+            999_999
+        );
+        // Every rounding goes the pool's way.
+        assert_eq!(math::get_amount_out(1, 1_000, 1_000, 300), 0);
+        assert_eq!(math::quote(1, 3, 1), 1);
+    }
+
+    #[test]
+    fn test_refresh_pool_decimals() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let wnear: AccountId = "wnear.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice, None);
+        let pool_id = create_pool(&mut contract, usdc, wnear);
+        let metadata = |decimals: PromiseResult| {
+            testing_env!(
+                VMContextBuilder::new().build(),
+                VMConfig::test(),
+                RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![
+                    PromiseResult::Successful(br#"{"spec":"ft-1.0.0","decimals":6}"#.to_vec()),
+                    decimals,
+                ],
+            );
+        };
+
+        metadata(PromiseResult::Failed);
+        contract.resolve_pool_decimals(pool_id);
+        assert_eq!(contract.get_pool(pool_id).decimals_1, None);
+
+        set_predecessor(&"bob.testnet".parse().unwrap());
+        contract.refresh_pool_decimals(pool_id);
+        metadata(PromiseResult::Successful(
+            br#"{"spec":"ft-1.0.0","decimals":24}"#.to_vec(),
+        ));
+        contract.resolve_pool_decimals(pool_id);
+        assert_eq!(contract.get_pool(pool_id).decimals_1, Some(24));
+
+        // Another failed fetch does not forget the decimals.
+        contract.refresh_pool_decimals(pool_id);
+        metadata(PromiseResult::Failed);
+        contract.resolve_pool_decimals(pool_id);
+        assert_eq!(contract.get_pool(pool_id).decimals_0, Some(6));
+        assert_eq!(contract.get_pool(pool_id).decimals_1, Some(24));
+    }
+
+    #[test]
+    fn test_pool_decimals_and_price() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let wnear: AccountId = "wnear.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice, None);
+        let pool_id = create_pool(&mut contract, usdc, wnear);
+        set_reserves(&mut contract, pool_id, 1_000_000_000, to_yocto(500).0);
+        assert_eq!(contract.get_price(pool_id), None);
+
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(br#"{"spec":"ft-1.0.0","decimals":6}"#.to_vec()),
+                PromiseResult::Successful(br#"{"name":"MATRIX","decimal":24}"#.to_vec()),
+            ],
+        );
+        contract.resolve_pool_decimals(pool_id);
+
+        let pool = contract.get_pool(pool_id);
+        assert_eq!(pool.decimals_0, Some(6));
+        assert_eq!(pool.decimals_1, Some(24));
+        // 1 USDC buys 0.5 NEAR.
+        assert_eq!(pool.price, Some(U128(500_000_000_000_000_000)));
+    }
+
+    #[test]
+    fn test_twap() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+        let metadata = br#"{"spec":"ft-1.0.0","decimals":24}"#.to_vec();
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(metadata.clone()),
+                PromiseResult::Successful(metadata),
+            ],
+        );
+        contract.resolve_pool_decimals(pool_id);
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(100),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        let at = |seconds: u64| {
+            testing_env!(VMContextBuilder::new()
+                .predecessor_account_id(alice.clone())
+                .block_timestamp(seconds * 1_000_000_000)
+                .build());
+        };
+
+        // One token_0 is worth one token_1 for 100 seconds, then less after the swap.
+        at(100);
+        contract.swap(pool_id, token_0.clone(), to_yocto(100), None, None);
+        let spot_0 = contract.get_price(pool_id).unwrap().0;
+        let spot_1 = 10u128.pow(36) / spot_0;
+
+        at(200);
+        let twap_0 = contract.get_twap(pool_id, token_0.clone(), 100).unwrap().0;
+        let twap_1 = contract.get_twap(pool_id, token_1.clone(), 100).unwrap().0;
+        assert!(spot_0.abs_diff(twap_0) <= 1);
+        assert!(spot_1.abs_diff(twap_1) <= 1);
+
+        let twap_0 = contract.get_twap(pool_id, token_0.clone(), 150).unwrap().0;
+        assert!(((10u128.pow(18) + 2 * spot_0) / 3).abs_diff(twap_0) <= 1);
+        let twap_0 = contract.get_twap(pool_id, token_0.clone(), 200).unwrap().0;
+        assert!(((10u128.pow(18) + spot_0) / 2).abs_diff(twap_0) <= 1);
+        // Nothing was observed before the pool was created.
+        assert_eq!(contract.get_twap(pool_id, token_0, 201), None);
+    }
+
+    #[test]
+    fn test_twap_with_more_observations() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let token_2: AccountId = "token_3.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_a = create_pool(&mut contract, token_0.clone(), token_1);
+        let pool_b = create_pool(&mut contract, token_0.clone(), token_2);
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_a, to_yocto(1_000).0, to_yocto(1_000).0);
+        set_reserves(&mut contract, pool_b, to_yocto(1_000).0, to_yocto(1_000).0);
+        for pool_id in [pool_a, pool_b] {
+            let metadata = br#"{"spec":"ft-1.0.0","decimals":24}"#.to_vec();
+            testing_env!(
+                VMContextBuilder::new().build(),
+                VMConfig::test(),
+                RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![
+                    PromiseResult::Successful(metadata.clone()),
+                    PromiseResult::Successful(metadata),
+                ],
+            );
+            contract.resolve_pool_decimals(pool_id);
+        }
+
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(100),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(to_yocto(1).0)
+            .build());
+        contract.increase_observation_cardinality(pool_a, 64);
+
+        // A swap every second writes 50 observations into each pool.
+        for seconds in 1..=50 {
+            testing_env!(VMContextBuilder::new()
+                .predecessor_account_id(alice.clone())
+                .block_timestamp(seconds * 1_000_000_000)
+                .build());
+            for pool_id in [pool_a, pool_b] {
+                contract.swap(pool_id, token_0.clone(), to_yocto(1), None, None);
+            }
+        }
+
+        testing_env!(VMContextBuilder::new()
+            .block_timestamp(60 * 1_000_000_000)
+            .build());
+        assert!(contract.get_twap(pool_a, token_0.clone(), 60).is_some());
+        // The 32 slots of the other pool only reach back to its 19th second.
+        assert!(contract.get_twap(pool_b, token_0.clone(), 41).is_some());
+        assert_eq!(contract.get_twap(pool_b, token_0, 42), None);
+    }
+
+    fn start_flash_loan(contract: &mut Contract, token_0: &AccountId, alice: &AccountId) -> u64 {
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let pool_id = create_pool(contract, token_0.clone(), token_1);
+        register(contract, alice);
+        set_reserves(contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        set_predecessor(token_0);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(10),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.flash_loan(
+            pool_id,
+            token_0.clone(),
+            to_yocto(9),
+            bob.clone(),
+            String::new(),
+        );
+        set_promise_result(PromiseResult::Successful(vec![]));
+        contract.resolve_flash_transfer(pool_id, bob, String::new());
+        pool_id
+    }
+
+    #[test]
+    fn test_flash_loan_repaid() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = start_flash_loan(&mut contract, &token_0, &alice);
+        // 9 * 300 / 9700, rounded up.
+        let fee = 278_350_515_463_917_525_773_196;
+        assert_eq!(
+            contract.get_deposit(alice.clone(), token_0.clone()).0,
+            to_yocto(1).0 - fee
+        );
+
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(1),
+            r#"{"action":"swap","pool_id":0}"#.to_string(),
+        );
+        assert_eq!(unused, to_yocto(1));
+
+        let unused = contract.ft_on_transfer(
+            "bob.testnet".parse().unwrap(),
+            U128(to_yocto(9).0 + fee),
+            r#"{"action":"flash_repay","pool_id":0}"#.to_string(),
+        );
+        assert_eq!(unused, U128(0));
+        set_promise_result(PromiseResult::Successful(vec![]));
+        contract.resolve_flash_loan(pool_id);
+
+        assert_eq!(contract.get_reserve_0(pool_id).0, to_yocto(1_000).0 + fee);
+        assert_eq!(contract.get_deposit(alice, token_0.clone()), to_yocto(10));
+        assert_eq!(contract.get_token_total(token_0).0, to_yocto(1_010).0 + fee);
+        assert!(!contract.internal_get_pool(pool_id).is_locked());
+    }
+
+    #[test]
+    fn test_flash_loan_not_repaid() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = start_flash_loan(&mut contract, &token_0, &alice);
+        let fee = 278_350_515_463_917_525_773_196;
+
+        // Short by one, so the collateral stays in the pool on top of the repayment.
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            "bob.testnet".parse().unwrap(),
+            U128(to_yocto(9).0 + fee - 1),
+            r#"{"action":"flash_repay","pool_id":0}"#.to_string(),
+        );
+        set_promise_result(PromiseResult::Successful(vec![]));
+        contract.resolve_flash_loan(pool_id);
+
+        assert_eq!(
+            contract.get_reserve_0(pool_id).0,
+            to_yocto(1_009).0 + 2 * fee - 1
+        );
+        assert_eq!(contract.get_deposit(alice, token_0).0, to_yocto(1).0 - fee);
+        assert!(!contract.internal_get_pool(pool_id).is_locked());
+    }
+
+    #[test]
+    fn test_flash_loan_callback_failed() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = start_flash_loan(&mut contract, &token_0, &alice);
+        let fee = 278_350_515_463_917_525_773_196;
+
+        // The receiver panicked without paying anything back, and emptying the
+        // borrower's deposits meanwhile would not have helped: the collateral is escrowed.
+        set_promise_result(PromiseResult::Failed);
+        contract.resolve_flash_loan(pool_id);
+
+        assert_eq!(contract.get_reserve_0(pool_id).0, to_yocto(1_000).0 + fee);
+        assert_eq!(
+            contract.get_deposit(alice, token_0.clone()).0,
+            to_yocto(1).0 - fee
+        );
+        assert_eq!(contract.get_token_total(token_0), to_yocto(1_001));
+        assert!(!contract.internal_get_pool(pool_id).is_locked());
+    }
+
+    #[test]
+    #[should_panic(expected = "NOT_ENOUGH_BALANCE")]
+    fn test_flash_loan_without_deposits() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice, None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        register(&mut contract, &bob);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build());
+        contract.flash_loan(pool_id, token_0, to_yocto(999), bob, String::new());
+    }
+
+    #[test]
+    fn test_unlock_pool() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = start_flash_loan(&mut contract, &token_0, &alice);
+        let fee = 278_350_515_463_917_525_773_196;
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.grant_role(Role::Operator, bob.clone());
+
+        // resolve_flash_loan never ran, e.g. because it ran out of gas.
+        set_predecessor(&token_0);
+        contract.ft_on_transfer(
+            bob.clone(),
+            U128(to_yocto(9).0 + fee),
+            r#"{"action":"flash_repay","pool_id":0}"#.to_string(),
+        );
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build());
+        contract.unlock_pool(pool_id);
+
+        assert_eq!(contract.get_reserve_0(pool_id).0, to_yocto(1_000).0 + fee);
+        assert_eq!(contract.get_deposit(alice, token_0), to_yocto(10));
+        assert!(!contract.internal_get_pool(pool_id).is_locked());
+    }
+
+    #[test]
+    #[should_panic(expected = "NOT_ALLOWED")]
+    fn test_unlock_pool_not_allowed() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = start_flash_loan(&mut contract, &token_0, &alice);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("bob.testnet".parse().unwrap())
+            .attached_deposit(1)
+            .build());
+        contract.unlock_pool(pool_id);
+    }
+
+    #[test]
+    fn test_add_liquidity_one_token() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(4_000).0);
+        let mut pool = contract.internal_get_pool(pool_id);
+        pool.total_supply = to_yocto(2_000).0;
+        contract.internal_save_pool(pool_id, &pool);
+
+        set_predecessor(&token_1);
+        contract.ft_on_transfer(
+            alice.clone(),
+            to_yocto(100),
+            r#"{"action":"deposit"}"#.to_string(),
+        );
+        set_predecessor(&alice);
+        let shares = contract
+            .add_liquidity_one_token(pool_id, token_1.clone(), to_yocto(50), None, None)
+            .0;
+
+        // Almost all of the 50 goes in, half of it swapped at roughly the pool's price.
+        assert!(shares > to_yocto(12).0 && shares < to_yocto(125).0 / 10);
+        assert_eq!(contract.get_balance_of(pool_id, alice.clone()).0, shares);
+        let dust_0 = contract.get_deposit(alice.clone(), token_0).0;
+        let dust_1 = contract.get_deposit(alice, token_1).0 - to_yocto(50).0;
+        assert!(dust_0 <= 1 && dust_1 <= 10);
+    }
+
+    #[test]
+    fn test_add_liquidity_one_token_by_transfer() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice, None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+        let mut pool = contract.internal_get_pool(pool_id);
+        pool.total_supply = to_yocto(1_000).0;
+        contract.internal_save_pool(pool_id, &pool);
+
+        // bob is not registered yet, so the transfer is refunded.
+        set_predecessor(&token_0);
+        let msg = r#"{"action":"add_liquidity_one_token","pool_id":0,"min_shares":"4900000000000000000000000"}"#;
+        let unused = contract.ft_on_transfer(bob.clone(), to_yocto(10), msg.to_string());
+        assert_eq!(unused, to_yocto(10));
+        assert_eq!(contract.get_balance_of(pool_id, bob.clone()), U128(0));
+
+        // Once registered, the rounding dust comes back with the transfer.
+        register(&mut contract, &bob);
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(bob.clone(), to_yocto(10), msg.to_string());
+        assert!(unused.0 <= 10);
+        assert!(contract.get_balance_of(pool_id, bob).0 >= 4_900_000_000_000_000_000_000_000);
+        assert_eq!(
+            contract.get_token_total(token_0.clone()).0,
+            to_yocto(1_010).0 - unused.0
+        );
+        assert_eq!(
+            contract.get_reserve_0(pool_id).0,
+            to_yocto(1_010).0 - unused.0
+        );
+    }
+
+    #[test]
+    fn test_remove_liquidity_one_token() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &alice);
+        contract.mint(pool_id, alice.clone(), to_yocto(100).0);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(400).0);
+
+        // 10 token_0 and 40 token_1 come out, the 40 are sold into the smaller pool.
+        let amount_out = to_yocto(10).0
+            + math::get_amount_out(to_yocto(40).0, to_yocto(360).0, to_yocto(90).0, 300);
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity_one_token(
+            pool_id,
+            to_yocto(10),
+            token_0.clone(),
+            U128(amount_out),
+        );
+
+        assert_eq!(
+            contract.get_balance_of(pool_id, alice.clone()),
+            to_yocto(90)
+        );
+        assert_eq!(contract.get_reserve_1(pool_id), to_yocto(400));
+        assert_eq!(
+            contract.get_reserve_0(pool_id).0,
+            to_yocto(100).0 - amount_out
+        );
+        assert_eq!(
+            contract.get_token_total(token_0).0,
+            to_yocto(100).0 - amount_out
+        );
+        assert_eq!(contract.get_deposits(alice).len(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "INSUFFICIENT_OUTPUT_AMOUNT")]
+    fn test_remove_liquidity_one_token_below_min_amount_out() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0, token_1.clone());
+        register(&mut contract, &alice);
+        contract.mint(pool_id, alice.clone(), to_yocto(100).0);
+        set_reserves(&mut contract, pool_id, to_yocto(100).0, to_yocto(400).0);
+
+        // A round trip through the pool costs the swap fee on half of the position.
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity_one_token(pool_id, to_yocto(10), token_1, to_yocto(80));
+    }
+
+    #[test]
+    fn test_add_liquidity_for_beneficiary_only_deposits() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let token_0: AccountId = "token_1.testnet".parse().unwrap();
+        let token_1: AccountId = "token_2.testnet".parse().unwrap();
+        let mut contract = Contract::init(alice.clone(), None);
+        let pool_id = create_pool(&mut contract, token_0.clone(), token_1.clone());
+        register(&mut contract, &bob);
+        set_reserves(&mut contract, pool_id, to_yocto(1_000).0, to_yocto(1_000).0);
+        for token_id in [&token_0, &token_1] {
+            set_predecessor(token_id);
+            contract.ft_on_transfer(
+                bob.clone(),
+                to_yocto(50),
+                r#"{"action":"deposit"}"#.to_string(),
+            );
+        }
+
+        set_predecessor(&token_0);
+        let unused = contract.ft_on_transfer(
+            alice,
+            U128(1),
+            r#"{"action":"add_liquidity","pool_id":0,"beneficiary_id":"bob.testnet"}"#.to_string(),
+        );
+        assert_eq!(unused, U128(0));
+        assert_eq!(contract.get_balance_of(pool_id, bob.clone()), U128(0));
+        assert_eq!(
+            contract.get_deposit(bob.clone(), token_0).0,
+            to_yocto(50).0 + 1
+        );
+        assert_eq!(contract.get_deposit(bob, token_1), to_yocto(50));
     }
 }
@@ -1,225 +1,672 @@
-use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
-use near_sdk::json_types::U128;
-use near_sdk::{
-    assert_one_yocto, ext_contract, near_bindgen, require, AccountId, Balance, Gas, PromiseOrValue,
-    PromiseResult,
-};
-use num_integer::Roots;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, near_bindgen, require, AccountId, Balance, Promise};
 use std::cmp;
 
-use crate::events::{LiquidityAdded, LiquidityRemoved, TokensSwaped};
+use crate::constants::{DEAD_ACCOUNT_ID, GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER};
+use crate::events::{LiquidityAdded, LiquidityRemoved, TokensSwaped, TokensWithdrawn};
 use crate::external::ext_ft_contract;
 use crate::market_inspect::MarketInspect;
 use crate::market_writer::MarketWriter;
-use crate::util::{get_yocto, to_dec, to_yocto};
+use crate::math::{
+    compute_initial_shares, compute_protocol_fee_shares, compute_root_k, compute_shares,
+    compute_swap_amount_for_one_token, compute_withdraw, get_amount_in, get_amount_out,
+    MINIMUM_LIQUIDITY,
+};
+use crate::pause::Operation;
+use crate::util::is_expired;
 
 use crate::*;
 
-pub const DENOM: u128 = 1_000_000_000_000_000_000_000_000;
-
 pub trait MarketMakerCore {
-    fn add_liquidity(&mut self) -> U128;
-    fn remove_liquidity(&mut self, shares: U128);
-    fn swap(&mut self, tokenIn: AccountId) -> U128;
+    fn add_liquidity(
+        &mut self,
+        pool_id: u64,
+        min_shares: Option<U128>,
+        max_amount_0: Option<U128>,
+        max_amount_1: Option<U128>,
+        deadline: Option<U64>,
+    ) -> U128;
+    fn add_liquidity_one_token(
+        &mut self,
+        pool_id: u64,
+        token_id: AccountId,
+        amount_in: U128,
+        min_shares: Option<U128>,
+        deadline: Option<U64>,
+    ) -> U128;
+    fn remove_liquidity(&mut self, pool_id: u64, _shares: U128);
+    fn remove_liquidity_one_token(
+        &mut self,
+        pool_id: u64,
+        shares: U128,
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> Promise;
+    fn swap(
+        &mut self,
+        pool_id: u64,
+        token_id: AccountId,
+        amount_in: U128,
+        min_amount_out: Option<U128>,
+        deadline: Option<U64>,
+    ) -> U128;
+    fn swap_exact_out(
+        &mut self,
+        pool_id: u64,
+        token_out: AccountId,
+        amount_out: U128,
+        max_amount_in: U128,
+        deadline: Option<U64>,
+    ) -> U128;
+    fn withdraw(&mut self, token_id: AccountId, amount: Option<U128>) -> Promise;
 }
 
 #[near_bindgen]
 impl MarketMakerCore for Contract {
-    fn add_liquidity(&mut self) -> U128 {
+    /// Adds as much of the caller's deposits as fits the current price. Whatever is
+    /// left over stays deposited and can be taken back with `withdraw`.
+    fn add_liquidity(
+        &mut self,
+        pool_id: u64,
+        min_shares: Option<U128>,
+        max_amount_0: Option<U128>,
+        max_amount_1: Option<U128>,
+        deadline: Option<U64>,
+    ) -> U128 {
+        self.assert_not_paused(Operation::AddLiquidity);
+        require!(!is_expired(deadline), "EXPIRED");
         let sender_id = env::predecessor_account_id();
-        let amount_0: Balance = self
-            .get_received_liquidity_amount(sender_id.clone(), self.token_0.clone())
-            .into();
-        let amount_1: Balance = self
-            .get_received_liquidity_amount(sender_id.clone(), self.token_1.clone())
-            .into();
+        U128(self.internal_add_liquidity(
+            pool_id,
+            &sender_id,
+            min_shares.map_or(0, |min_shares| min_shares.0),
+            max_amount_0.map(|max_amount_0| max_amount_0.0),
+            max_amount_1.map(|max_amount_1| max_amount_1.0),
+        ))
+    }
 
-        self.set_received_liquidity_amount(sender_id.clone(), self.get_token_0());
-        self.set_received_liquidity_amount(sender_id.clone(), self.get_token_1());
+    /// Adds `amount_in` of the caller's deposits of `token_id` alone. The pool first buys
+    /// as much of the other token with it as keeps both sides balanced, then shares are
+    /// minted for the result. The dust left over by rounding stays deposited.
+    fn add_liquidity_one_token(
+        &mut self,
+        pool_id: u64,
+        token_id: AccountId,
+        amount_in: U128,
+        min_shares: Option<U128>,
+        deadline: Option<U64>,
+    ) -> U128 {
+        self.assert_not_paused(Operation::Swap);
+        self.assert_not_paused(Operation::AddLiquidity);
+        require!(!is_expired(deadline), "EXPIRED");
+        let sender_id = env::predecessor_account_id();
+        self.internal_withdraw(&sender_id, &token_id, amount_in.0);
+        let (shares, unused) = self.internal_add_liquidity_one_token(
+            pool_id,
+            &sender_id,
+            &token_id,
+            amount_in.0,
+            min_shares.map_or(0, |min_shares| min_shares.0),
+        );
+        // Only what was spent leaves the deposits.
+        if unused > 0 {
+            self.internal_deposit(&sender_id, &token_id, unused);
+        }
+        U128(shares)
+    }
 
-        if self.reserve_0 > 0_u128 || self.reserve_1 > 0_u128 {
-            require!(
-                self.reserve_0.checked_mul(amount_1.into())
-                    == self.reserve_1.checked_mul(amount_0.into()),
-                "x / y != dx / dy"
-            );
+    /// Burns `shares` and credits the caller's part of both reserves to its deposits.
+    fn remove_liquidity(&mut self, pool_id: u64, _shares: U128) {
+        self.assert_not_paused(Operation::RemoveLiquidity);
+        let sender_id = env::predecessor_account_id();
+
+        // `_shares` is the argument name clients already send.
+        let (amount_0, amount_1) = self.internal_remove_liquidity(pool_id, &sender_id, _shares.0);
+
+        let pool = self.internal_get_pool(pool_id);
+        self.internal_deposit(&sender_id, &pool.token_0, amount_0);
+        self.internal_deposit(&sender_id, &pool.token_1, amount_1);
+    }
+
+    /// Burns `shares`, sells the caller's part of the other reserve back to the pool and
+    /// sends the caller all of its part in `token_out`, which has to be at least
+    /// `min_amount_out`.
+    #[payable]
+    fn remove_liquidity_one_token(
+        &mut self,
+        pool_id: u64,
+        shares: U128,
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> Promise {
+        assert_one_yocto();
+        self.assert_not_paused(Operation::RemoveLiquidity);
+        self.assert_not_paused(Operation::Swap);
+        let sender_id = env::predecessor_account_id();
+        let pool = self.internal_get_pool(pool_id);
+        let token_in = pool.other_token(&token_out);
+
+        let (amount_0, amount_1) = self.internal_remove_liquidity(pool_id, &sender_id, shares.0);
+        let (amount_in, amount_out) = if token_out == pool.token_0 {
+            (amount_1, amount_0)
+        } else {
+            (amount_0, amount_1)
+        };
+        let amount_out =
+            amount_out + self.internal_swap(pool_id, &token_in, amount_in, 0, &sender_id);
+        require!(amount_out >= min_amount_out.0, "INSUFFICIENT_OUTPUT_AMOUNT");
+
+        TokensWithdrawn {
+            account_id: &sender_id,
+            token_id: &token_out,
+            amount: &amount_out.into(),
         }
+        .emit();
 
-        let amount_0_dec = to_dec(amount_0);
+        self.internal_send_tokens(&token_out, &sender_id, amount_out, "REMOVE_LIQUIDITY")
+    }
 
-        let amount_1_dec = to_dec(amount_1);
+    /// Sells `amount_in` of the caller's deposited `token_id` and credits the output to
+    /// its deposits.
+    fn swap(
+        &mut self,
+        pool_id: u64,
+        token_id: AccountId,
+        amount_in: U128,
+        min_amount_out: Option<U128>,
+        deadline: Option<U64>,
+    ) -> U128 {
+        self.assert_not_paused(Operation::Swap);
+        require!(!is_expired(deadline), "EXPIRED");
+        let pool = self.internal_get_pool(pool_id);
+        let token_out = pool.other_token(&token_id);
 
-        let shares: Balance = if self.total_supply == 0_u128 {
-            to_yocto(
-                (amount_0_dec
-                    .checked_mul(amount_1_dec)
-                    .unwrap_or_else(|| env::panic_str("failed_add_liquidity__0")))
-                .sqrt(),
-            )
-            .into()
+        let sender_id = env::predecessor_account_id();
+
+        require!(amount_in > U128(0), "AMOUNT_0");
+
+        self.internal_withdraw(&sender_id, &token_id, amount_in.0);
+
+        let _amount_out = self.internal_swap(
+            pool_id,
+            &token_id,
+            amount_in.into(),
+            min_amount_out.unwrap_or(U128(0)).0,
+            &sender_id,
+        );
+
+        self.internal_deposit(&sender_id, &token_out, _amount_out);
+
+        U128(_amount_out)
+    }
+
+    /// Buys exactly `amount_out` of `token_out` with the caller's deposits of the other
+    /// token, spending at most `max_amount_in`. Returns the amount spent; the rest of the
+    /// deposit stays where it is.
+    fn swap_exact_out(
+        &mut self,
+        pool_id: u64,
+        token_out: AccountId,
+        amount_out: U128,
+        max_amount_in: U128,
+        deadline: Option<U64>,
+    ) -> U128 {
+        self.assert_not_paused(Operation::Swap);
+        require!(!is_expired(deadline), "EXPIRED");
+        let token_in = self.internal_get_pool(pool_id).other_token(&token_out);
+
+        let sender_id = env::predecessor_account_id();
+
+        require!(amount_out > U128(0), "AMOUNT_0");
+
+        let amount_in = self.internal_swap_exact_out(
+            pool_id,
+            &token_out,
+            amount_out.0,
+            max_amount_in.0,
+            &sender_id,
+        );
+
+        self.internal_withdraw(&sender_id, &token_in, amount_in);
+        self.internal_deposit(&sender_id, &token_out, amount_out.0);
+
+        U128(amount_in)
+    }
+
+    /// Sends deposited tokens back to the caller, all of them when `amount` is not set.
+    /// Keeps working while the contract is paused.
+    #[payable]
+    fn withdraw(&mut self, token_id: AccountId, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+
+        let sender_id = env::predecessor_account_id();
+        let amount: Balance = amount.map_or_else(
+            || self.internal_get_account(&sender_id).get_balance(&token_id),
+            |amount| amount.0,
+        );
+
+        require!(amount > 0, "AMOUNT_0");
+
+        self.internal_withdraw(&sender_id, &token_id, amount);
+
+        TokensWithdrawn {
+            account_id: &sender_id,
+            token_id: &token_id,
+            amount: &amount.into(),
+        }
+        .emit();
+
+        self.internal_send_tokens(&token_id, &sender_id, amount, "WITHDRAW")
+    }
+}
+
+impl Contract {
+    /// Turns the deposits of `sender_id` into shares, using at most `max_amount_0` /
+    /// `max_amount_1` of them.
+    pub(crate) fn internal_add_liquidity(
+        &mut self,
+        pool_id: u64,
+        sender_id: &AccountId,
+        min_shares: Balance,
+        max_amount_0: Option<Balance>,
+        max_amount_1: Option<Balance>,
+    ) -> Balance {
+        let pool = self.internal_get_pool(pool_id);
+
+        let account = self.internal_get_account(sender_id);
+        let deposit_0 = account.get_balance(&pool.token_0);
+        let deposit_1 = account.get_balance(&pool.token_1);
+
+        let available_0 = max_amount_0.map_or(deposit_0, |max| cmp::min(deposit_0, max));
+        let available_1 = max_amount_1.map_or(deposit_1, |max| cmp::min(deposit_1, max));
+        let (amount_0, amount_1) = pool.liquidity_amounts(available_0, available_1);
+
+        self.internal_withdraw(sender_id, &pool.token_0, amount_0);
+        self.internal_withdraw(sender_id, &pool.token_1, amount_1);
+        self.internal_mint_liquidity(pool_id, sender_id, amount_0, amount_1, min_shares)
+    }
+
+    /// Adds `amount_0` and `amount_1`, already taken from wherever they came from, to
+    /// the reserves and mints the shares for them to `account_id`, whose storage balance
+    /// pays for a new share entry.
+    fn internal_mint_liquidity(
+        &mut self,
+        pool_id: u64,
+        account_id: &AccountId,
+        amount_0: Balance,
+        amount_1: Balance,
+        min_shares: Balance,
+    ) -> Balance {
+        self.internal_mint_protocol_fee(pool_id);
+        let pool = self.internal_get_pool(pool_id);
+
+        let initial_storage = env::storage_usage();
+        let shares: Balance = if pool.total_supply == 0_u128 {
+            let initial_shares = compute_initial_shares(amount_0, amount_1);
+            require!(
+                initial_shares > MINIMUM_LIQUIDITY,
+                "INSUFFICIENT_INITIAL_LIQUIDITY"
+            );
+            self.mint(
+                pool_id,
+                AccountId::new_unchecked(DEAD_ACCOUNT_ID.to_string()),
+                MINIMUM_LIQUIDITY,
+            );
+            initial_shares - MINIMUM_LIQUIDITY
         } else {
-            let _amount_0 = amount_0_dec
-                .checked_mul(to_dec(self.total_supply))
-                .unwrap_or_else(|| env::panic_str("failed_add_liquidity__1"))
-                .checked_div(to_dec(self.reserve_0))
-                .unwrap_or_else(|| env::panic_str("failed_add_liquidity__2"));
-
-            let _amount_1 = amount_1_dec
-                .checked_mul(to_dec(self.total_supply))
-                .unwrap_or_else(|| env::panic_str("failed_add_liquidity__3"))
-                .checked_div(to_dec(self.reserve_1))
-                .unwrap_or_else(|| env::panic_str("failed_add_liquidity__4"));
-
-            to_yocto(cmp::min(_amount_0, _amount_1)).into()
+            compute_shares(
+                amount_0,
+                amount_1,
+                pool.reserve_0,
+                pool.reserve_1,
+                pool.total_supply,
+            )
         };
 
         require!(shares > 0, "shares_0");
+        require!(shares >= min_shares, "INSUFFICIENT_SHARES");
 
-        self.mint(sender_id.clone(), shares.clone());
+        // The first provider also pays for the entry of the locked shares.
+        self.mint(pool_id, account_id.clone(), shares);
+        self.internal_charge_storage(account_id, initial_storage);
 
-        self.update_reserve_0();
-        self.update_reserve_1();
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_unlocked();
+        pool.record_observation();
+        pool.reserve_0 += amount_0;
+        pool.reserve_1 += amount_1;
+        self.internal_save_pool(pool_id, &pool);
+        self.internal_set_root_k_last(pool_id, pool.reserve_0, pool.reserve_1);
 
         LiquidityAdded {
-            account_id: &sender_id,
+            pool_id,
+            account_id,
             shares: &shares.into(),
             amount_0: &amount_0.into(),
             amount_1: &amount_1.into(),
         }
         .emit();
 
-        U128(shares)
+        shares
     }
 
-    fn remove_liquidity(&mut self, _shares: U128) {
-        let sender_id = env::predecessor_account_id();
-
+    /// Burns `shares` of `sender_id` and takes its part of both reserves out of the pool.
+    /// The caller decides where the amounts go.
+    pub(crate) fn internal_remove_liquidity(
+        &mut self,
+        pool_id: u64,
+        sender_id: &AccountId,
+        shares: Balance,
+    ) -> (Balance, Balance) {
+        let sender_id = sender_id.clone();
         require!(
-            _shares <= self.get_balance_of(sender_id.clone()),
+            shares <= self.get_balance_of(pool_id, sender_id.clone()).0,
             "no_balance"
         );
 
-        let shares: Balance = _shares.into();
+        self.internal_mint_protocol_fee(pool_id);
+        let pool = self.internal_get_pool(pool_id);
 
-        self.burn(sender_id.clone(), shares.clone());
+        let amount_0 = compute_withdraw(shares, pool.reserve_0, pool.total_supply);
+        let amount_1 = compute_withdraw(shares, pool.reserve_1, pool.total_supply);
 
-        let shares_dec = to_dec(shares);
-
-        let amount_0: Balance = to_yocto(
-            ((shares_dec.checked_mul(to_dec(self.reserve_0)))
-                .unwrap_or_else(|| env::panic_str("failed_remove_liquidity__0")))
-                / to_dec(self.total_supply),
-        )
-        .into();
-
-        let amount_1: Balance = to_yocto(
-            ((shares_dec.checked_mul(to_dec(self.reserve_1)))
-                .unwrap_or_else(|| env::panic_str("failed_remove_liquidity__1")))
-                / to_dec(self.total_supply),
-        )
-        .into();
+        let initial_storage = env::storage_usage();
+        self.burn(pool_id, sender_id.clone(), shares);
+        self.internal_charge_storage(&sender_id, initial_storage);
 
         require!(amount_0 > 0 && amount_1 > 0, "amount_0 = 0 or amount_1 = 0");
 
-        ext_ft_contract::ext(self.get_token_0())
-            .with_attached_deposit(1)
-            .ft_transfer(
-                sender_id.clone(),
-                amount_0.into(),
-                Some("transfer amount_0".to_string()),
-            );
-
-        ext_ft_contract::ext(self.get_token_1())
-            .with_attached_deposit(1)
-            .ft_transfer(
-                sender_id.clone(),
-                amount_1.into(),
-                Some("transfer amount_1".to_string()),
-            );
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_unlocked();
+        pool.record_observation();
+        pool.reserve_0 -= amount_0;
+        pool.reserve_1 -= amount_1;
+        self.internal_save_pool(pool_id, &pool);
+        self.internal_set_root_k_last(pool_id, pool.reserve_0, pool.reserve_1);
 
         LiquidityRemoved {
+            pool_id,
             account_id: &sender_id,
             shares: &shares.into(),
             amount_0: &amount_0.into(),
             amount_1: &amount_1.into(),
         }
         .emit();
+
+        (amount_0, amount_1)
     }
 
-    fn swap(&mut self, token_id: AccountId) -> U128 {
+    /// Turns `amount_in` of `token_in`, already held by the contract, into shares for
+    /// `account_id`, selling the part of it that balances both sides first. Returns the
+    /// shares and the part of `amount_in` left over by rounding. The little of the other
+    /// token left over goes to the deposits of `account_id`.
+    pub(crate) fn internal_add_liquidity_one_token(
+        &mut self,
+        pool_id: u64,
+        account_id: &AccountId,
+        token_in: &AccountId,
+        amount_in: Balance,
+        min_shares: Balance,
+    ) -> (Balance, Balance) {
+        let pool = self.internal_get_pool(pool_id);
+        let token_out = pool.other_token(token_in);
+        require!(amount_in > 0, "AMOUNT_0");
         require!(
-            token_id == self.get_token_0() || token_id == self.token_1,
-            "INVALID_TOKEN"
+            pool.reserve_0 > 0 && pool.reserve_1 > 0,
+            "INSUFFICIENT_LIQUIDITY"
         );
 
-        let sender_id = env::predecessor_account_id();
-
-        require!(
-            self.get_received_swap_amount(sender_id.clone(), token_id.clone()) > U128(0),
-            "AMOUNT_0"
-        );
+        let is_token_0 = token_in == &pool.token_0;
+        let reserve_in = if is_token_0 {
+            pool.reserve_0
+        } else {
+            pool.reserve_1
+        };
+        let swap_amount =
+            compute_swap_amount_for_one_token(amount_in, reserve_in, self.fee_config.fee_bps);
+        let amount_out = self.internal_swap(pool_id, token_in, swap_amount, 0, account_id);
 
-        let amount_in = self.get_received_swap_amount(sender_id.clone(), token_id.clone());
-        self.set_received_swap_amount(sender_id.clone(), token_id.clone());
+        let pool = self.internal_get_pool(pool_id);
+        let (available_0, available_1) = if is_token_0 {
+            (amount_in - swap_amount, amount_out)
+        } else {
+            (amount_out, amount_in - swap_amount)
+        };
+        let (amount_0, amount_1) = pool.liquidity_amounts(available_0, available_1);
+        let shares =
+            self.internal_mint_liquidity(pool_id, account_id, amount_0, amount_1, min_shares);
 
-        let is_token_0 = token_id == self.get_token_0();
+        let (unused_in, unused_out) = if is_token_0 {
+            (available_0 - amount_0, available_1 - amount_1)
+        } else {
+            (available_1 - amount_1, available_0 - amount_0)
+        };
+        if unused_out > 0 {
+            self.internal_deposit(account_id, &token_out, unused_out);
+        }
+        (shares, unused_in)
+    }
 
-        let (token_in, token_out, reserve_in, reserve_out) = if is_token_0 {
-            (
-                self.get_token_0(),
-                self.get_token_1(),
-                self.reserve_0,
-                self.reserve_1,
+    /// Sends `amount` of `token_id` out of the contract. Should the transfer fail, the
+    /// amount is credited back to the deposits of `receiver_id` instead of being lost.
+    pub(crate) fn internal_send_tokens(
+        &mut self,
+        token_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) -> Promise {
+        self.internal_start_transfer(token_id, amount);
+
+        ext_ft_contract::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id.clone(), amount.into(), Some(memo.to_string()))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .resolve_transfer(token_id.clone(), receiver_id.clone(), amount.into()),
             )
+    }
+
+    /// Amount of the other token the pool pays out for `amount_in` of `token_in`, after fees.
+    pub(crate) fn internal_get_amount_out(
+        &self,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount_in: Balance,
+    ) -> Balance {
+        let pool = self.internal_get_pool(pool_id);
+        pool.assert_has_token(token_in);
+
+        let (reserve_in, reserve_out) = if token_in == &pool.token_0 {
+            (pool.reserve_0, pool.reserve_1)
         } else {
-            (
-                self.get_token_1(),
-                self.get_token_0(),
-                self.reserve_0,
-                self.reserve_1,
-            )
+            (pool.reserve_1, pool.reserve_0)
         };
 
-        let _amount_in_dec = to_dec(amount_in.into());
-        let _reserve_in_dec = to_dec(reserve_in);
-        let _reserve_out_dec = to_dec(reserve_out);
-
-        let amount_in_with_fees = (_amount_in_dec.checked_mul(97))
-            .unwrap_or_else(|| env::panic_str("failed_amount_in_with_fees__0"))
-            .checked_div(100)
-            .unwrap_or_else(|| env::panic_str("failed_amount_in_with_fees_1"));
-
-        let _amount_out = to_yocto(
-            (_reserve_out_dec
-                .checked_mul(amount_in_with_fees)
-                .unwrap_or_else(|| env::panic_str("failed_amount_out__0")))
-            .checked_div(
-                _reserve_in_dec
-                    .checked_add(amount_in_with_fees)
-                    .unwrap_or_else(|| env::panic_str("failed_amount_out__1")),
-            )
-            .unwrap_or_else(|| env::panic_str("failed_amount_out__2")),
+        get_amount_out(amount_in, reserve_in, reserve_out, self.fee_config.fee_bps)
+    }
+
+    /// Amount of the other token the pool needs to pay out `amount_out` of `token_out`,
+    /// after fees.
+    pub(crate) fn internal_get_amount_in(
+        &self,
+        pool_id: u64,
+        token_out: &AccountId,
+        amount_out: Balance,
+    ) -> Balance {
+        let pool = self.internal_get_pool(pool_id);
+        pool.assert_has_token(token_out);
+
+        let (reserve_in, reserve_out) = if token_out == &pool.token_0 {
+            (pool.reserve_1, pool.reserve_0)
+        } else {
+            (pool.reserve_0, pool.reserve_1)
+        };
+
+        get_amount_in(amount_out, reserve_in, reserve_out, self.fee_config.fee_bps)
+    }
+
+    /// Protocol shares accrued since the last liquidity change and not minted yet.
+    pub(crate) fn internal_get_unminted_protocol_fee(&self, pool_id: u64) -> Balance {
+        let pool = self.internal_get_pool(pool_id);
+        compute_protocol_fee_shares(
+            pool.total_supply,
+            compute_root_k(pool.reserve_0, pool.reserve_1),
+            pool.root_k_last,
+            self.fee_config.protocol_share_bps(),
+        )
+    }
+
+    /// Mints the accrued protocol shares to `fee_to`. Must run before every change of the
+    /// total supply, so that the fee is computed against the supply it was earned on.
+    pub(crate) fn internal_mint_protocol_fee(&mut self, pool_id: u64) {
+        let shares = self.internal_get_unminted_protocol_fee(pool_id);
+        if let (Some(fee_to), true) = (self.fee_config.fee_to.clone(), shares > 0) {
+            self.mint(pool_id, fee_to, shares);
+            let mut pool = self.internal_get_pool(pool_id);
+            pool.protocol_fee_shares += shares;
+            self.internal_save_pool(pool_id, &pool);
+        }
+    }
+
+    /// Remembers the invariant the next protocol fee is measured from, given the reserves
+    /// the pool ends up with after a liquidity change.
+    pub(crate) fn internal_set_root_k_last(
+        &mut self,
+        pool_id: u64,
+        reserve_0: Balance,
+        reserve_1: Balance,
+    ) {
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.root_k_last = if self.fee_config.protocol_share_bps() > 0 {
+            compute_root_k(reserve_0, reserve_1)
+        } else {
+            0
+        };
+        self.internal_save_pool(pool_id, &pool);
+    }
+
+    /// Sells `amount_in` of `token_in`, already held by the contract, against the pool's
+    /// reserves. The caller decides where the output goes.
+    pub(crate) fn internal_swap(
+        &mut self,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount_in: Balance,
+        min_amount_out: Balance,
+        account_id: &AccountId,
+    ) -> Balance {
+        let _amount_out = self.internal_get_amount_out(pool_id, token_in, amount_in);
+
+        require!(
+            _amount_out > 0 && _amount_out >= min_amount_out,
+            "INSUFFICIENT_OUTPUT_AMOUNT"
         );
 
-        ext_ft_contract::ext(token_out)
-            .with_attached_deposit(1)
-            .ft_transfer(
-                sender_id.clone(),
-                _amount_out.clone(),
-                Some("TRANSFER_SWAPPED_TOKEN".to_string()),
-            );
+        self.internal_apply_swap(pool_id, token_in, amount_in, _amount_out, account_id);
+
+        _amount_out
+    }
+
+    /// Buys exactly `amount_out` of `token_out` for at most `max_amount_in` of the other
+    /// token, already held by the contract. Returns the input spent.
+    pub(crate) fn internal_swap_exact_out(
+        &mut self,
+        pool_id: u64,
+        token_out: &AccountId,
+        amount_out: Balance,
+        max_amount_in: Balance,
+        account_id: &AccountId,
+    ) -> Balance {
+        let amount_in = self.internal_get_amount_in(pool_id, token_out, amount_out);
+
+        require!(amount_in <= max_amount_in, "EXCESSIVE_INPUT_AMOUNT");
 
-        self.update_reserve_0();
-        self.update_reserve_1();
+        let token_in = self.internal_get_pool(pool_id).other_token(token_out);
+        self.internal_apply_swap(pool_id, &token_in, amount_in, amount_out, account_id);
+
+        amount_in
+    }
+
+    /// Token and amount the pools of `pool_ids` pay out, one after the other, for
+    /// `amount_in` of `token_in`. `None` if the route is empty, does not connect or
+    /// visits a pool twice. As no pool is visited twice, this is exactly what
+    /// `internal_swap_route` pays out.
+    pub(crate) fn internal_get_route_amount_out(
+        &self,
+        pool_ids: &[u64],
+        token_in: &AccountId,
+        amount_in: Balance,
+    ) -> Option<(AccountId, Balance)> {
+        if pool_ids.is_empty() {
+            return None;
+        }
+
+        let mut token_id = token_in.clone();
+        let mut amount = amount_in;
+        for (index, pool_id) in pool_ids.iter().enumerate() {
+            let pool = self.pools.get(*pool_id)?;
+            if pool_ids[..index].contains(pool_id) || !pool.has_token(&token_id) {
+                return None;
+            }
+            amount = self.internal_get_amount_out(*pool_id, &token_id, amount);
+            token_id = pool.other_token(&token_id);
+        }
+        Some((token_id, amount))
+    }
+
+    /// Sells `amount_in` of `token_in` through a route checked with
+    /// `internal_get_route_amount_out`. Intermediate amounts go straight from one pool's
+    /// reserves into the next.
+    pub(crate) fn internal_swap_route(
+        &mut self,
+        pool_ids: &[u64],
+        token_in: &AccountId,
+        amount_in: Balance,
+        account_id: &AccountId,
+    ) -> Balance {
+        let mut token_id = token_in.clone();
+        let mut amount = amount_in;
+        for pool_id in pool_ids {
+            let token_out = self.internal_get_pool(*pool_id).other_token(&token_id);
+            amount = self.internal_swap(*pool_id, &token_id, amount, 0, account_id);
+            token_id = token_out;
+        }
+        amount
+    }
+
+    /// Moves `amount_in` into and `amount_out` out of the pool's reserves.
+    fn internal_apply_swap(
+        &mut self,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount_in: Balance,
+        amount_out: Balance,
+        account_id: &AccountId,
+    ) {
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_unlocked();
+        pool.record_observation();
+
+        if token_in == &pool.token_0 {
+            pool.reserve_0 += amount_in;
+            pool.reserve_1 -= amount_out;
+        } else {
+            pool.reserve_1 += amount_in;
+            pool.reserve_0 -= amount_out;
+        }
+        self.internal_save_pool(pool_id, &pool);
+        let price = pool.price(pool.reserve_0, pool.reserve_1);
 
         TokensSwaped {
-            account_id: &sender_id,
-            token_in: &token_in.into(),
-            amount_out: &_amount_out.into(),
+            pool_id,
+            account_id,
+            token_in,
+            amount_out: &amount_out.into(),
+            price,
         }
         .emit();
-
-        _amount_out
     }
 }
@@ -1,64 +1,122 @@
-use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
 use near_sdk::json_types::U128;
-use near_sdk::{
-    assert_one_yocto, ext_contract, near_bindgen, AccountId, Balance, Gas, PromiseOrValue,
-    PromiseResult,
-};
+use near_sdk::{near_bindgen, AccountId};
+use std::collections::HashMap;
 
+use crate::market_types::{FeeConfig, PoolInfo};
 use crate::*;
 
 pub trait MarketInspect {
-    fn get_reserve_0(&self) -> U128;
-    fn get_reserve_1(&self) -> U128;
-    fn get_token_0(&self) -> AccountId;
-    fn get_token_1(&self) -> AccountId;
-    fn get_total_supply(&self) -> U128;
-    fn get_balance_of(&self, account_id: AccountId) -> U128;
-    fn get_received_liquidity_amount(&self, account_id: AccountId, token_id: AccountId) -> U128;
-    fn get_received_swap_amount(&self, account_id: AccountId, token_id: AccountId) -> U128;
+    fn get_number_of_pools(&self) -> u64;
+    fn get_pool(&self, pool_id: u64) -> PoolInfo;
+    fn get_pool_id(&self, token_0: AccountId, token_1: AccountId) -> Option<u64>;
+    fn get_price(&self, pool_id: u64) -> Option<U128>;
+    fn get_reserve_0(&self, pool_id: u64) -> U128;
+    fn get_reserve_1(&self, pool_id: u64) -> U128;
+    fn get_token_0(&self, pool_id: u64) -> AccountId;
+    fn get_token_1(&self, pool_id: u64) -> AccountId;
+    fn get_total_supply(&self, pool_id: u64) -> U128;
+    fn get_balance_of(&self, pool_id: u64, account_id: AccountId) -> U128;
+    fn get_deposit(&self, account_id: AccountId, token_id: AccountId) -> U128;
+    fn get_deposits(&self, account_id: AccountId) -> HashMap<AccountId, U128>;
     fn get_current_account_id(&self) -> AccountId;
+    fn get_fee_config(&self) -> FeeConfig;
+    fn get_token_total(&self, token_id: AccountId) -> U128;
+    fn get_protocol_fees(&self, pool_id: u64) -> U128;
+    fn get_amount_out(&self, pool_id: u64, token_in: AccountId, amount_in: U128) -> U128;
+    fn get_amount_in(&self, pool_id: u64, token_out: AccountId, amount_out: U128) -> U128;
+    fn get_twap(&self, pool_id: u64, token_id: AccountId, window_seconds: u64) -> Option<U128>;
 }
 
 #[near_bindgen]
 impl MarketInspect for Contract {
-    fn get_reserve_0(&self) -> U128 {
-        self.reserve_0.into()
+    fn get_number_of_pools(&self) -> u64 {
+        self.pools.len()
     }
-    fn get_reserve_1(&self) -> U128 {
-        self.reserve_1.into()
+    fn get_pool(&self, pool_id: u64) -> PoolInfo {
+        self.internal_get_pool(pool_id).info()
     }
-    fn get_token_0(&self) -> AccountId {
-        self.token_0.clone()
+    fn get_pool_id(&self, token_0: AccountId, token_1: AccountId) -> Option<u64> {
+        self.pool_ids.get(&get_pair_key(&token_0, &token_1))
     }
-    fn get_token_1(&self) -> AccountId {
-        self.token_1.clone()
+    fn get_price(&self, pool_id: u64) -> Option<U128> {
+        let pool = self.internal_get_pool(pool_id);
+        pool.price(pool.reserve_0, pool.reserve_1)
     }
-    fn get_total_supply(&self) -> U128 {
-        self.total_supply.into()
+    fn get_reserve_0(&self, pool_id: u64) -> U128 {
+        self.internal_get_pool(pool_id).reserve_0.into()
     }
-    fn get_balance_of(&self, account_id: AccountId) -> U128 {
-        self.balance.get(&account_id).unwrap_or(0).into()
+    fn get_reserve_1(&self, pool_id: u64) -> U128 {
+        self.internal_get_pool(pool_id).reserve_1.into()
     }
-    fn get_received_liquidity_amount(&self, account_id: AccountId, token_id: AccountId) -> U128 {
-        if let Some(liquidity) = self.received_liquidity_amount.get(&account_id) {
-            let received = liquidity.get(&token_id).unwrap_or_default();
-            return received.amount.into();
-        }
-
-        U128(0)
+    fn get_token_0(&self, pool_id: u64) -> AccountId {
+        self.internal_get_pool(pool_id).token_0
+    }
+    fn get_token_1(&self, pool_id: u64) -> AccountId {
+        self.internal_get_pool(pool_id).token_1
+    }
+    fn get_total_supply(&self, pool_id: u64) -> U128 {
+        self.internal_get_pool(pool_id).total_supply.into()
+    }
+    fn get_balance_of(&self, pool_id: u64, account_id: AccountId) -> U128 {
+        self.internal_get_pool(pool_id)
+            .shares
+            .get(&account_id)
+            .unwrap_or(0)
+            .into()
+    }
+    fn get_deposit(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        self.internal_get_account(&account_id)
+            .get_balance(&token_id)
+            .into()
     }
 
-    fn get_received_swap_amount(&self, account_id: AccountId, token_id: AccountId) -> U128 {
-        if let Some(liquidity) = self.received_swap_amount.get(&account_id) {
-            let received = liquidity.get(&token_id).unwrap_or_default();
-            return received.amount.into();
-        }
-
-        U128(0)
+    /// Every token `account_id` holds in the AMM outside of pools.
+    fn get_deposits(&self, account_id: AccountId) -> HashMap<AccountId, U128> {
+        self.internal_get_account(&account_id)
+            .tokens
+            .iter()
+            .map(|(token_id, balance)| (token_id, balance.into()))
+            .collect()
     }
 
     fn get_current_account_id(&self) -> AccountId {
         env::current_account_id()
     }
+
+    fn get_fee_config(&self) -> FeeConfig {
+        self.fee_config.clone()
+    }
+
+    /// Amount of `token_id` held for pool reserves and deposits.
+    fn get_token_total(&self, token_id: AccountId) -> U128 {
+        self.internal_get_token_total(&token_id).into()
+    }
+
+    /// Pool shares earned by the protocol, both minted already and still accruing.
+    fn get_protocol_fees(&self, pool_id: u64) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        (pool.protocol_fee_shares + self.internal_get_unminted_protocol_fee(pool_id)).into()
+    }
+
+    /// What `swap` would pay out for `amount_in` of `token_in` right now, after fees.
+    fn get_amount_out(&self, pool_id: u64, token_in: AccountId, amount_in: U128) -> U128 {
+        self.internal_get_amount_out(pool_id, &token_in, amount_in.0)
+            .into()
+    }
+
+    /// Least amount of the other token a swap needs right now to pay out `amount_out` of
+    /// `token_out`, after fees.
+    fn get_amount_in(&self, pool_id: u64, token_out: AccountId, amount_out: U128) -> U128 {
+        self.internal_get_amount_in(pool_id, &token_out, amount_out.0)
+            .into()
+    }
+
+    /// Price of one whole `token_id` in the other token, scaled like `get_price` and
+    /// averaged over the last `window_seconds`. Unlike the spot price it cannot be moved
+    /// within a single block. `None` while the decimals are unknown or the pool's
+    /// observations do not reach back that far.
+    fn get_twap(&self, pool_id: u64, token_id: AccountId, window_seconds: u64) -> Option<U128> {
+        self.internal_get_pool(pool_id)
+            .twap(&token_id, window_seconds)
+    }
 }
@@ -1,37 +1,210 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::json_types::Base64VecU8;
+use near_sdk::json_types::U64;
 use near_sdk::serde::{Deserialize, Serialize};
 
+use crate::math::{DEFAULT_FEE_BPS, FEE_DIVISOR};
+use crate::pause::Operation;
 use crate::*;
 
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
-pub struct LiquidityReceived {
-    pub received: bool,
-    pub amount: U128,
+pub struct PoolInfo {
+    pub token_0: AccountId,
+    pub token_1: AccountId,
+    pub reserve_0: U128,
+    pub reserve_1: U128,
+    pub total_supply: U128,
+    pub decimals_0: Option<u8>,
+    pub decimals_1: Option<u8>,
+    /// Price of one whole token_0 in whole token_1, with `PRICE_DECIMALS` decimals.
+    pub price: Option<U128>,
 }
 
-impl Default for LiquidityReceived {
-    fn default() -> Self {
-        Self {
-            received: false,
-            amount: U128(0),
+/// The part of the NEP-148 metadata the AMM reads from a token.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FungibleTokenMetadata {
+    #[serde(alias = "decimal")]
+    pub decimals: u8,
+}
+
+/// NEP-148 metadata of a pool's shares.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SharesMetadata {
+    pub spec: String,
+    pub name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<String>,
+    pub decimals: u8,
+}
+
+pub const TRANSFER_MESSAGE_VERSION: u8 = 1;
+
+fn default_transfer_message_version() -> u8 {
+    TRANSFER_MESSAGE_VERSION
+}
+
+/// JSON message accepted by `ft_on_transfer`, e.g.
+/// `{"version":1,"action":"swap","pool_id":0,"min_amount_out":"100"}`.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferMessage {
+    #[serde(default = "default_transfer_message_version")]
+    pub version: u8,
+    #[serde(flatten)]
+    pub action: TransferAction,
+}
+
+/// Actions of version 1 of the transfer message. Deposits are credited to
+/// `beneficiary_id` when it is set, otherwise to the sender. Actions with a `deadline`,
+/// a block timestamp in nanoseconds, are refunded once it has passed.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TransferAction {
+    /// Adds the tokens to the deposits of the beneficiary, to be used by `swap` and
+    /// `add_liquidity` or taken back with `withdraw`.
+    Deposit { beneficiary_id: Option<AccountId> },
+    /// Sells the transferred tokens in the same receipt and sends the output to
+    /// `receiver_id`, or to the sender when it is not set.
+    Swap {
+        pool_id: u64,
+        min_amount_out: Option<U128>,
+        receiver_id: Option<AccountId>,
+        deadline: Option<U64>,
+    },
+    /// Buys exactly `amount_out` of the other token with the transferred tokens and sends
+    /// it to `receiver_id`, or to the sender. The unspent part of the transfer is refunded.
+    SwapExactOut {
+        pool_id: u64,
+        amount_out: U128,
+        receiver_id: Option<AccountId>,
+        deadline: Option<U64>,
+    },
+    /// Sells the transferred tokens through every pool of `pool_ids` in turn and sends
+    /// only the final output to `receiver_id`, or to the sender.
+    SwapRoute {
+        pool_ids: Vec<u64>,
+        min_amount_out: Option<U128>,
+        receiver_id: Option<AccountId>,
+        deadline: Option<U64>,
+    },
+    /// Deposits the tokens and, once the sender holds both tokens of the pool, mints at
+    /// least `min_shares` shares from no more than the given maximum amounts, and no more
+    /// of the transferred token than came with the transfer. For another beneficiary the
+    /// tokens are only deposited.
+    AddLiquidity {
+        pool_id: u64,
+        min_shares: Option<U128>,
+        max_amount_0: Option<U128>,
+        max_amount_1: Option<U128>,
+        beneficiary_id: Option<AccountId>,
+        deadline: Option<U64>,
+    },
+    /// Adds the tokens alone, like `add_liquidity_one_token`, and mints the shares to the
+    /// beneficiary, which has to be registered. The dust of the transferred token left
+    /// over is refunded.
+    AddLiquidityOneToken {
+        pool_id: u64,
+        min_shares: Option<U128>,
+        beneficiary_id: Option<AccountId>,
+        deadline: Option<U64>,
+    },
+    /// Pays back the flash loan of the pool, in either of its tokens. Refunded while no
+    /// loan is in flight.
+    FlashRepay { pool_id: u64 },
+}
+
+impl TransferAction {
+    pub fn pool_id(&self) -> Option<u64> {
+        match self {
+            TransferAction::Deposit { .. } => None,
+            TransferAction::Swap { pool_id, .. }
+            | TransferAction::SwapExactOut { pool_id, .. }
+            | TransferAction::AddLiquidity { pool_id, .. }
+            | TransferAction::AddLiquidityOneToken { pool_id, .. }
+            | TransferAction::FlashRepay { pool_id } => Some(*pool_id),
+            TransferAction::SwapRoute { pool_ids, .. } => pool_ids.first().copied(),
+        }
+    }
+
+    pub fn deadline(&self) -> Option<U64> {
+        match self {
+            TransferAction::Deposit { .. } | TransferAction::FlashRepay { .. } => None,
+            TransferAction::Swap { deadline, .. }
+            | TransferAction::SwapExactOut { deadline, .. }
+            | TransferAction::SwapRoute { deadline, .. }
+            | TransferAction::AddLiquidity { deadline, .. }
+            | TransferAction::AddLiquidityOneToken { deadline, .. } => *deadline,
+        }
+    }
+
+    /// The account the output of a swap goes to, which must be registered: should the
+    /// transfer out fail, the output is credited to its deposits instead.
+    pub fn receiver_id<'a>(&'a self, sender_id: &'a AccountId) -> Option<&'a AccountId> {
+        match self {
+            TransferAction::Swap { receiver_id, .. }
+            | TransferAction::SwapExactOut { receiver_id, .. }
+            | TransferAction::SwapRoute { receiver_id, .. } => {
+                Some(receiver_id.as_ref().unwrap_or(sender_id))
+            }
+            _ => None,
+        }
+    }
+
+    /// The pool operations the action feeds into. Plain deposits and repayments are
+    /// always accepted.
+    pub fn operations(&self) -> &'static [Operation] {
+        match self {
+            TransferAction::Deposit { .. } | TransferAction::FlashRepay { .. } => &[],
+            TransferAction::Swap { .. }
+            | TransferAction::SwapExactOut { .. }
+            | TransferAction::SwapRoute { .. } => &[Operation::Swap],
+            TransferAction::AddLiquidity { .. } => &[Operation::AddLiquidity],
+            TransferAction::AddLiquidityOneToken { .. } => {
+                &[Operation::Swap, Operation::AddLiquidity]
+            }
         }
     }
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+/// Swap fee of every pool and the part of it that goes to the protocol.
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Debug, PartialEq)]
 #[serde(crate = "near_sdk::serde")]
-pub struct SwapReceived {
-    pub amount: U128,
-    pub token_in: AccountId,
+pub struct FeeConfig {
+    /// Fee taken from the input of every swap, in basis points.
+    pub fee_bps: u32,
+    /// Part of the swap fee minted as shares to `fee_to`, in basis points of the fee.
+    pub protocol_share_bps: u32,
+    /// Receiver of the protocol fee. No protocol fee is taken while unset.
+    pub fee_to: Option<AccountId>,
 }
 
-impl Default for SwapReceived {
+impl Default for FeeConfig {
     fn default() -> Self {
         Self {
-            amount: U128(0),
-            token_in: "".parse().unwrap(),
+            fee_bps: DEFAULT_FEE_BPS,
+            protocol_share_bps: 0,
+            fee_to: None,
+        }
+    }
+}
+
+impl FeeConfig {
+    pub fn assert_valid(&self) {
+        require!(self.fee_bps < FEE_DIVISOR, "INVALID_FEE");
+        require!(self.protocol_share_bps <= FEE_DIVISOR, "INVALID_FEE");
+    }
+
+    /// Share of the fee taken by the protocol, zero while it is switched off.
+    pub fn protocol_share_bps(&self) -> u32 {
+        if self.fee_to.is_some() {
+            self.protocol_share_bps
+        } else {
+            0
         }
     }
 }
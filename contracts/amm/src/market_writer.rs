@@ -1,136 +1,127 @@
-use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
 use near_sdk::json_types::U128;
-use near_sdk::{
-    assert_one_yocto, ext_contract, near_bindgen, AccountId, Balance, Gas, PromiseOrValue,
-    PromiseResult,
-};
+use near_sdk::{near_bindgen, AccountId, Balance, PromiseResult};
 
-use crate::constants::{GAS_FOR_FT_TRANSFER_CALL, GAS_FOR_RESOLVE_TRANSFER};
-use crate::external::ext_ft_contract;
-use crate::market_inspect::MarketInspect;
+use crate::events::{MftBurn, MftMint, PoolDecimalsSet, TransferFailed};
+use crate::market_types::FungibleTokenMetadata;
 use crate::*;
 
 pub(crate) trait MarketWriter {
-    fn update_reserve_0(&mut self);
-    fn update_reserve_1(&mut self);
-    fn resolve_reserve_0(&mut self) -> U128;
-    fn resolve_reserve_1(&mut self) -> U128;
-    fn mint(&mut self, sender_id: AccountId, shares: Balance);
-    fn burn(&mut self, sender_id: AccountId, shares: Balance);
-    fn set_received_liquidity_amount(&mut self, account_id: AccountId, token: AccountId);
-    fn set_received_swap_amount(&mut self, sender_id: AccountId, token_id: AccountId);
+    #[allow(dead_code)]
+    fn resolve_pool_decimals(&mut self, pool_id: u64);
+    fn mint(&mut self, pool_id: u64, sender_id: AccountId, shares: Balance);
+    fn burn(&mut self, pool_id: u64, sender_id: AccountId, shares: Balance);
+    #[allow(dead_code)]
+    fn resolve_transfer(&mut self, token_id: AccountId, receiver_id: AccountId, amount: U128);
 }
 
 #[near_bindgen]
 impl MarketWriter for Contract {
-    fn update_reserve_0(&mut self) {
-        let current_account_id = self.get_current_account_id();
-        ext_ft_contract::ext(self.token_0.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
-            .ft_balance_of(current_account_id.clone())
-            .then(
-                Self::ext(current_account_id)
-                    .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
-                    .resolve_reserve_0(),
-            );
-    }
-
-    fn update_reserve_1(&mut self) {
-        let current_account_id = self.get_current_account_id();
-        ext_ft_contract::ext(self.token_1.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
-            .ft_balance_of(current_account_id.clone())
-            .then(
-                Self::ext(current_account_id)
-                    .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
-                    .resolve_reserve_1(),
-            );
+    #[private]
+    fn resolve_pool_decimals(&mut self, pool_id: u64) {
+        assert_eq!(
+            env::promise_results_count(),
+            2,
+            "Expected 2 promise results"
+        );
+
+        let decimals: Vec<Option<u8>> = (0..2)
+            .map(|index| match env::promise_result(index) {
+                PromiseResult::Successful(result) => {
+                    near_sdk::serde_json::from_slice::<FungibleTokenMetadata>(&result)
+                        .ok()
+                        .map(|metadata| metadata.decimals)
+                }
+                _ => None,
+            })
+            .collect();
+
+        // A failed fetch keeps what the pool already knows.
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.decimals_0 = decimals[0].or(pool.decimals_0);
+        pool.decimals_1 = decimals[1].or(pool.decimals_1);
+        self.internal_save_pool(pool_id, &pool);
+
+        if decimals.contains(&None) {
+            env::log_str("pool_decimals_update_failed");
+        }
+        if let (Some(decimals_0), Some(decimals_1)) = (pool.decimals_0, pool.decimals_1) {
+            PoolDecimalsSet {
+                pool_id,
+                decimals_0,
+                decimals_1,
+            }
+            .emit();
+        }
     }
 
     #[private]
-    fn mint(&mut self, sender_id: AccountId, shares: Balance) {
-        let mut _balance = self.balance.get(&sender_id).unwrap_or(0);
+    fn mint(&mut self, pool_id: u64, sender_id: AccountId, shares: Balance) {
+        let mut pool = self.internal_get_pool(pool_id);
+        let _balance = pool.shares.get(&sender_id).unwrap_or(0);
         let new_balance = _balance
             .checked_add(shares)
             .unwrap_or_else(|| env::panic_str("FAILED_MINT_SHARES"));
-        self.balance.insert(&sender_id, &new_balance);
-        self.total_supply = self.total_supply + shares;
+        pool.shares.insert(&sender_id, &new_balance);
+        pool.total_supply += shares;
+        self.internal_save_pool(pool_id, &pool);
+
+        MftMint {
+            pool_id,
+            owner_id: &sender_id,
+            amount: &shares.into(),
+            memo: None,
+        }
+        .emit();
     }
 
-    fn burn(&mut self, sender_id: AccountId, shares: Balance) {
-        let mut _balance = self.balance.get(&sender_id).unwrap_or(0);
+    #[private]
+    fn burn(&mut self, pool_id: u64, sender_id: AccountId, shares: Balance) {
+        let mut pool = self.internal_get_pool(pool_id);
+        let _balance = pool.shares.get(&sender_id).unwrap_or(0);
         let new_balance = _balance
             .checked_sub(shares)
             .unwrap_or_else(|| env::panic_str("FAILED_BURN_SHARES"));
-        self.balance.insert(&sender_id, &new_balance);
-        self.total_supply = self.total_supply - shares;
+        if new_balance == 0 {
+            pool.shares.remove(&sender_id);
+        } else {
+            pool.shares.insert(&sender_id, &new_balance);
+        }
+        pool.total_supply -= shares;
+        self.internal_save_pool(pool_id, &pool);
+
+        MftBurn {
+            pool_id,
+            owner_id: &sender_id,
+            amount: &shares.into(),
+            memo: None,
+        }
+        .emit();
     }
 
     #[private]
-    fn resolve_reserve_0(&mut self) -> U128 {
+    fn resolve_transfer(&mut self, token_id: AccountId, receiver_id: AccountId, amount: U128) {
         assert_eq!(env::promise_results_count(), 1, "Expected 1 promise result");
+        self.internal_finish_transfer(&token_id, amount.0);
 
         match env::promise_result(0) {
             PromiseResult::NotReady => {
                 unreachable!()
             }
 
-            PromiseResult::Successful(result) => {
-                if let Ok(balance) = near_sdk::serde_json::from_slice::<U128>(&result) {
-                    self.reserve_0 = balance.into();
-                    balance
-                } else {
-                    env::panic_str("reserve_0_update_failed")
-                }
-            }
-
-            PromiseResult::Failed => U128(0),
-        }
-    }
-
-    #[private]
-    fn resolve_reserve_1(&mut self) -> U128 {
-        assert_eq!(env::promise_results_count(), 1, "Expected 1 promise result");
+            PromiseResult::Successful(_) => {}
 
-        match env::promise_result(0) {
-            PromiseResult::NotReady => {
-                unreachable!()
-            }
+            // The tokens never left, so they go back to the receiver's deposits.
+            PromiseResult::Failed => {
+                self.internal_increase_token_total(&token_id, amount.0);
+                self.internal_deposit_unchecked(&receiver_id, &token_id, amount.0);
 
-            PromiseResult::Successful(result) => {
-                if let Ok(balance) = near_sdk::serde_json::from_slice::<U128>(&result) {
-                    self.reserve_1 = balance.into();
-                    balance
-                } else {
-                    env::panic_str("reserve_1_update_failed")
+                TransferFailed {
+                    token_id: &token_id,
+                    receiver_id: &receiver_id,
+                    amount: &amount,
                 }
+                .emit();
             }
-
-            PromiseResult::Failed => U128(0),
-        }
-    }
-
-    #[private]
-    fn set_received_liquidity_amount(&mut self, sender_id: AccountId, token_id: AccountId) {
-        if let Some(_liquidity_item) = self.received_liquidity_amount.get(&sender_id) {
-            let mut liquidity_item = _liquidity_item;
-            let mut received = liquidity_item.get(&token_id).unwrap_or_default();
-            received.amount = U128(0);
-            liquidity_item.insert(&token_id, &received);
-            self.received_liquidity_amount
-                .insert(&sender_id, &liquidity_item);
-        }
-    }
-
-    #[private]
-    fn set_received_swap_amount(&mut self, sender_id: AccountId, token_id: AccountId) {
-        if let Some(_swap_item) = self.received_swap_amount.get(&sender_id) {
-            let mut swap_item = _swap_item;
-            let mut received = swap_item.get(&token_id).unwrap_or_default();
-            received.amount = U128(0);
-            swap_item.insert(&token_id, &received);
-            self.received_swap_amount.insert(&sender_id, &swap_item);
         }
     }
 }
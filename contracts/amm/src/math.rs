@@ -0,0 +1,268 @@
+use near_sdk::{env, Balance};
+
+#[allow(clippy::all)]
+mod uint_types {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+    uint::construct_uint! {
+        pub struct U512(8);
+    }
+}
+
+pub use uint_types::{U256, U512};
+
+/// Decimals of the human-normalized prices reported by views and events.
+pub const PRICE_DECIMALS: u8 = 18;
+
+/// Fees are expressed in basis points of this divisor.
+pub const FEE_DIVISOR: u32 = 10_000;
+
+/// Swap fee of a freshly initialized contract, 3%.
+pub const DEFAULT_FEE_BPS: u32 = 300;
+
+/// Shares locked forever when a pool is first funded, so that the price of a share can
+/// never be pushed up far enough to round later deposits down to nothing.
+pub const MINIMUM_LIQUIDITY: Balance = 1_000;
+
+fn to_balance(value: U256) -> Balance {
+    if value > U256::from(u128::MAX) {
+        env::panic_str("MATH_OVERFLOW")
+    }
+    value.as_u128()
+}
+
+fn to_u512(value: U256) -> U512 {
+    let mut words = [0; 8];
+    words[..4].copy_from_slice(&value.0);
+    U512(words)
+}
+
+/// `a * b / c`, rounded down.
+pub fn mul_div(a: Balance, b: Balance, c: Balance) -> Balance {
+    if c == 0 {
+        env::panic_str("DIVISION_BY_ZERO")
+    }
+    to_balance(U256::from(a) * U256::from(b) / U256::from(c))
+}
+
+/// `a * b / c`, rounded up.
+pub fn mul_div_ceil(a: Balance, b: Balance, c: Balance) -> Balance {
+    if c == 0 {
+        env::panic_str("DIVISION_BY_ZERO")
+    }
+    let c = U256::from(c);
+    to_balance((U256::from(a) * U256::from(b) + c - 1) / c)
+}
+
+/// Shares minted for the first deposit of a pool, `sqrt(amount_0 * amount_1)` rounded down.
+pub fn compute_initial_shares(amount_0: Balance, amount_1: Balance) -> Balance {
+    compute_root_k(amount_0, amount_1)
+}
+
+/// `sqrt(reserve_0 * reserve_1)` rounded down, the pool's liquidity invariant.
+pub fn compute_root_k(reserve_0: Balance, reserve_1: Balance) -> Balance {
+    to_balance((U256::from(reserve_0) * U256::from(reserve_1)).integer_sqrt())
+}
+
+/// Shares minted for a deposit into a pool that already has liquidity. The smaller side
+/// counts and the result is rounded down, so the depositor never gets more than paid for.
+pub fn compute_shares(
+    amount_0: Balance,
+    amount_1: Balance,
+    reserve_0: Balance,
+    reserve_1: Balance,
+    total_supply: Balance,
+) -> Balance {
+    std::cmp::min(
+        mul_div(amount_0, total_supply, reserve_0),
+        mul_div(amount_1, total_supply, reserve_1),
+    )
+}
+
+/// Part of `reserve` owed for burning `shares`, rounded down.
+pub fn compute_withdraw(shares: Balance, reserve: Balance, total_supply: Balance) -> Balance {
+    mul_div(shares, reserve, total_supply)
+}
+
+/// Amount of the other token that matches `amount` at the current price, rounded up so
+/// the pool is never short-changed.
+pub fn quote(amount: Balance, reserve: Balance, reserve_other: Balance) -> Balance {
+    mul_div_ceil(amount, reserve_other, reserve)
+}
+
+/// Constant product output for `amount_in` after a swap fee of `fee_bps`, rounded down.
+pub fn get_amount_out(
+    amount_in: Balance,
+    reserve_in: Balance,
+    reserve_out: Balance,
+    fee_bps: u32,
+) -> Balance {
+    let amount_in_with_fee = U256::from(amount_in) * U256::from(FEE_DIVISOR - fee_bps);
+    let denominator = U256::from(reserve_in) * U256::from(FEE_DIVISOR) + amount_in_with_fee;
+    if denominator.is_zero() {
+        env::panic_str("DIVISION_BY_ZERO")
+    }
+    to_balance(amount_in_with_fee * U256::from(reserve_out) / denominator)
+}
+
+/// Input needed to get `amount_out` after a swap fee of `fee_bps`, the inverse of
+/// `get_amount_out`. Rounded up, so that selling it yields at least `amount_out`.
+pub fn get_amount_in(
+    amount_out: Balance,
+    reserve_in: Balance,
+    reserve_out: Balance,
+    fee_bps: u32,
+) -> Balance {
+    if amount_out >= reserve_out {
+        env::panic_str("INSUFFICIENT_LIQUIDITY")
+    }
+    let numerator = (U256::from(reserve_in) * U256::from(amount_out))
+        .checked_mul(U256::from(FEE_DIVISOR))
+        .unwrap_or_else(|| env::panic_str("MATH_OVERFLOW"));
+    let denominator = U256::from(reserve_out - amount_out) * U256::from(FEE_DIVISOR - fee_bps);
+    if denominator.is_zero() {
+        env::panic_str("DIVISION_BY_ZERO")
+    }
+    to_balance((numerator + denominator - 1) / denominator)
+}
+
+/// Shares owed to the protocol for the fees collected while `root_k_last` grew to `root_k`,
+/// where the protocol takes `protocol_share_bps` of the fee. This is Uniswap v2's `_mintFee`
+/// with the share made configurable, rounded down.
+pub fn compute_protocol_fee_shares(
+    total_supply: Balance,
+    root_k: Balance,
+    root_k_last: Balance,
+    protocol_share_bps: u32,
+) -> Balance {
+    if root_k_last == 0 || root_k <= root_k_last || protocol_share_bps == 0 {
+        return 0;
+    }
+    let share = U256::from(protocol_share_bps);
+    let numerator = U256::from(total_supply)
+        .checked_mul(U256::from(root_k - root_k_last) * share)
+        .unwrap_or_else(|| env::panic_str("MATH_OVERFLOW"));
+    let denominator =
+        U256::from(root_k) * (U256::from(FEE_DIVISOR) - share) + U256::from(root_k_last) * share;
+    to_balance(numerator / denominator)
+}
+
+/// Price of one whole token_0 in whole token_1, scaled by `10^PRICE_DECIMALS`. `None` when
+/// a reserve is empty or the value does not fit.
+pub fn compute_price(
+    reserve_0: Balance,
+    reserve_1: Balance,
+    decimals_0: u8,
+    decimals_1: u8,
+) -> Option<Balance> {
+    if reserve_0 == 0 || reserve_1 == 0 {
+        return None;
+    }
+    let scale_up = i32::from(decimals_0) + i32::from(PRICE_DECIMALS) - i32::from(decimals_1);
+    let ten = U256::from(10);
+    let (numerator, denominator) = if scale_up >= 0 {
+        (
+            U256::from(reserve_1).checked_mul(ten.checked_pow(U256::from(scale_up))?)?,
+            U256::from(reserve_0),
+        )
+    } else {
+        (
+            U256::from(reserve_1),
+            U256::from(reserve_0).checked_mul(ten.checked_pow(U256::from(-scale_up))?)?,
+        )
+    };
+    let price = numerator / denominator;
+    if price > U256::from(u128::MAX) {
+        return None;
+    }
+    Some(price.as_u128())
+}
+
+/// `reserve_out / reserve_in` as a Q64.64 fixed point number, the raw price the oracle
+/// accumulates. Zero while a reserve is empty.
+pub fn compute_raw_price_x64(reserve_in: Balance, reserve_out: Balance) -> U256 {
+    if reserve_in == 0 || reserve_out == 0 {
+        return U256::zero();
+    }
+    (U256::from(reserve_out) << 64) / U256::from(reserve_in)
+}
+
+/// Average over `elapsed` nanoseconds of the raw price whose cumulative value grew by
+/// `cumulative_delta`, normalized like `compute_price` for a token with `decimals_in`
+/// priced in one with `decimals_out`. `None` when the value does not fit.
+pub fn compute_twap(
+    cumulative_delta: U256,
+    elapsed: u64,
+    decimals_in: u8,
+    decimals_out: u8,
+) -> Option<Balance> {
+    if elapsed == 0 {
+        return None;
+    }
+    let average = cumulative_delta / U256::from(elapsed);
+    let scale_up = i32::from(decimals_in) + i32::from(PRICE_DECIMALS) - i32::from(decimals_out);
+    let ten = U256::from(10);
+    let price = if scale_up >= 0 {
+        average.checked_mul(ten.checked_pow(U256::from(scale_up))?)? >> 64
+    } else {
+        (average / ten.checked_pow(U256::from(-scale_up))?) >> 64
+    };
+    if price > U256::from(u128::MAX) {
+        return None;
+    }
+    Some(price.as_u128())
+}
+
+/// Fee on a flash loan of `amount`, rounded up. Repaying `amount` plus this fee keeps the
+/// pool's invariant exactly like a swap paying `fee_bps` would.
+pub fn compute_flash_loan_fee(amount: Balance, fee_bps: u32) -> Balance {
+    mul_div_ceil(
+        amount,
+        Balance::from(fee_bps),
+        Balance::from(FEE_DIVISOR - fee_bps),
+    )
+}
+
+/// Whether a pool that lent `borrowed_0` and `borrowed_1` out of its reserves and got
+/// `repaid_0` and `repaid_1` back keeps its constant product, with the swap fee taken from
+/// what came back. This is the check at the end of Uniswap v2's `swap`, so repaying in
+/// the other token amounts to a swap.
+#[allow(clippy::too_many_arguments)]
+pub fn flash_loan_invariant_holds(
+    reserve_0: Balance,
+    reserve_1: Balance,
+    borrowed_0: Balance,
+    borrowed_1: Balance,
+    repaid_0: Balance,
+    repaid_1: Balance,
+    fee_bps: u32,
+) -> bool {
+    let fee_divisor = U256::from(FEE_DIVISOR);
+    let adjusted = |reserve: Balance, borrowed: Balance, repaid: Balance| {
+        let balance = U256::from(reserve) - U256::from(borrowed) + U256::from(repaid);
+        to_u512(balance * fee_divisor - U256::from(repaid) * U256::from(fee_bps))
+    };
+    let k = to_u512(U256::from(reserve_0) * U256::from(reserve_1));
+    let fee_divisor = to_u512(fee_divisor);
+    adjusted(reserve_0, borrowed_0, repaid_0) * adjusted(reserve_1, borrowed_1, repaid_1)
+        >= k * fee_divisor * fee_divisor
+}
+
+/// Part of `amount_in` to sell into a pool holding `reserve_in` so that the rest and the
+/// output match the reserves after the swap, which is what adding liquidity from a single
+/// token needs. Solves `s^2 * g + s * r * h = a * r * F` for `s`, with `g = F - fee_bps`
+/// and `h = 2 * F - fee_bps`, rounded down.
+pub fn compute_swap_amount_for_one_token(
+    amount_in: Balance,
+    reserve_in: Balance,
+    fee_bps: u32,
+) -> Balance {
+    let reserve = to_u512(U256::from(reserve_in));
+    let fee_divisor = U512::from(FEE_DIVISOR);
+    let g = U512::from(FEE_DIVISOR - fee_bps);
+    let h = fee_divisor * U512::from(2) - U512::from(fee_bps);
+    let a = to_u512(U256::from(amount_in));
+    let root = (reserve * (reserve * h * h + a * g * fee_divisor * U512::from(4))).integer_sqrt();
+    ((root - reserve * h) / (g * U512::from(2))).as_u128()
+}
@@ -0,0 +1,87 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::Balance;
+
+use crate::math::{compute_raw_price_x64, U256};
+
+/// Observations a pool keeps from the start. Like Uniswap v3 at most one is written per
+/// block, so this is how many blocks with reserve changes a TWAP window can reach back
+/// over, until `increase_observation_cardinality` buys more.
+pub const INITIAL_OBSERVATION_CARDINALITY: u64 = 32;
+
+/// Most observations a pool can keep, as in Uniswap v3.
+pub const MAX_OBSERVATION_CARDINALITY: u64 = 65_535;
+
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Cumulative prices of a pool at a block timestamp, as in Uniswap v2's
+/// `price0CumulativeLast`: the sum of the raw Q64.64 price times the nanoseconds it was
+/// held. The sums wrap around, only differences between two observations mean anything.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct Observation {
+    /// Block timestamp in nanoseconds.
+    pub timestamp: u64,
+    /// Price of token_0 in token_1, as the words of a `U256`.
+    price_0_cumulative: [u64; 4],
+    /// Price of token_1 in token_0, as the words of a `U256`.
+    price_1_cumulative: [u64; 4],
+}
+
+impl Observation {
+    pub fn new(timestamp: u64) -> Self {
+        Self {
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    pub fn price_0_cumulative(&self) -> U256 {
+        U256(self.price_0_cumulative)
+    }
+
+    pub fn price_1_cumulative(&self) -> U256 {
+        U256(self.price_1_cumulative)
+    }
+
+    /// The observation at `timestamp`, the pool having held `reserve_0` and `reserve_1`
+    /// since this one. Periods without liquidity count as a price of zero.
+    pub fn next(&self, timestamp: u64, reserve_0: Balance, reserve_1: Balance) -> Self {
+        let elapsed = U256::from(timestamp - self.timestamp);
+        let accumulate = |cumulative: U256, price: U256| {
+            cumulative
+                .overflowing_add(price.overflowing_mul(elapsed).0)
+                .0
+        };
+        Self {
+            timestamp,
+            price_0_cumulative: accumulate(
+                self.price_0_cumulative(),
+                compute_raw_price_x64(reserve_0, reserve_1),
+            )
+            .0,
+            price_1_cumulative: accumulate(
+                self.price_1_cumulative(),
+                compute_raw_price_x64(reserve_1, reserve_0),
+            )
+            .0,
+        }
+    }
+
+    /// The observation at `timestamp`, which lies between this one and `newer`. The
+    /// reserves did not change in between, so the prices grew linearly.
+    pub fn interpolate(&self, newer: &Self, timestamp: u64) -> Self {
+        let elapsed = U256::from(newer.timestamp - self.timestamp);
+        let part = U256::from(timestamp - self.timestamp);
+        let interpolate = |older: U256, newer: U256| {
+            older
+                .overflowing_add(newer.overflowing_sub(older).0 / elapsed * part)
+                .0
+        };
+        Self {
+            timestamp,
+            price_0_cumulative: interpolate(self.price_0_cumulative(), newer.price_0_cumulative())
+                .0,
+            price_1_cumulative: interpolate(self.price_1_cumulative(), newer.price_1_cumulative())
+                .0,
+        }
+    }
+}
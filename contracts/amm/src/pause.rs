@@ -0,0 +1,91 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen};
+
+use crate::access_control::Role;
+use crate::events::{Paused, Unpaused};
+use crate::*;
+
+/// Groups of pool operations that can be stopped independently.
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Swap,
+    AddLiquidity,
+    RemoveLiquidity,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct PauseFlags {
+    pub swap: bool,
+    pub add_liquidity: bool,
+    pub remove_liquidity: bool,
+}
+
+impl PauseFlags {
+    fn flag(&mut self, operation: Operation) -> &mut bool {
+        match operation {
+            Operation::Swap => &mut self.swap,
+            Operation::AddLiquidity => &mut self.add_liquidity,
+            Operation::RemoveLiquidity => &mut self.remove_liquidity,
+        }
+    }
+
+    pub fn is_paused(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Swap => self.swap,
+            Operation::AddLiquidity => self.add_liquidity,
+            Operation::RemoveLiquidity => self.remove_liquidity,
+        }
+    }
+}
+
+pub trait Pausable {
+    fn pause(&mut self, operations: Vec<Operation>);
+    fn unpause(&mut self, operations: Vec<Operation>);
+    fn is_paused(&self, operation: Operation) -> bool;
+}
+
+/// Circuit breaker for every pool. `withdraw` keeps working while paused, so
+/// deposits can always be taken back.
+#[near_bindgen]
+impl Pausable for Contract {
+    #[payable]
+    fn pause(&mut self, operations: Vec<Operation>) {
+        assert_one_yocto();
+        self.assert_role(Role::Pauser);
+        for operation in &operations {
+            *self.paused.flag(*operation) = true;
+        }
+        Paused {
+            operations: &operations,
+        }
+        .emit();
+    }
+
+    #[payable]
+    fn unpause(&mut self, operations: Vec<Operation>) {
+        assert_one_yocto();
+        self.assert_role(Role::Pauser);
+        for operation in &operations {
+            *self.paused.flag(*operation) = false;
+        }
+        Unpaused {
+            operations: &operations,
+        }
+        .emit();
+    }
+
+    fn is_paused(&self, operation: Operation) -> bool {
+        self.paused.is_paused(operation)
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_paused(&self, operation: Operation) {
+        require!(!self.paused.is_paused(operation), "PAUSED");
+    }
+}
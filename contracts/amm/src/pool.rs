@@ -0,0 +1,242 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::json_types::U128;
+use near_sdk::{env, require, AccountId, Balance};
+use std::cmp;
+
+use crate::flash_loan::FlashLoan;
+use crate::market_types::PoolInfo;
+use crate::math::{compute_price, compute_twap, quote};
+use crate::oracle::{
+    Observation, INITIAL_OBSERVATION_CARDINALITY, MAX_OBSERVATION_CARDINALITY, NANOS_PER_SECOND,
+};
+use crate::StorageKey;
+
+/// A single constant product pair hosted by the contract.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Pool {
+    pub token_0: AccountId,
+    pub token_1: AccountId,
+    pub reserve_0: Balance,
+    pub reserve_1: Balance,
+    pub total_supply: Balance,
+    /// Filled in from the tokens' `ft_metadata` once the pool is created.
+    pub decimals_0: Option<u8>,
+    pub decimals_1: Option<u8>,
+    /// `sqrt(reserve_0 * reserve_1)` right after the last liquidity change, zero while the
+    /// protocol fee is off.
+    pub root_k_last: Balance,
+    /// Shares minted to the protocol fee receiver so far.
+    pub protocol_fee_shares: Balance,
+    pub shares: LookupMap<AccountId, Balance>,
+    /// Ring buffer of price observations. Every slot is allocated, and paid for, before
+    /// it is written.
+    pub observations: Vector<Observation>,
+    /// Slot of the newest observation.
+    pub observation_index: u64,
+    /// Slots written so far, up to `observation_cardinality`.
+    pub observation_count: u64,
+    /// Slots the ring buffer wraps around at. Grows to the allocated slots once the
+    /// newest observation reaches its end, so that older ones are not overwritten early.
+    pub observation_cardinality: u64,
+    /// The flash loan in flight, which locks the reserves.
+    pub flash_loan: Option<FlashLoan>,
+}
+
+impl Pool {
+    pub fn new(pool_id: u64, token_0: AccountId, token_1: AccountId) -> Self {
+        let mut observations =
+            Vector::new(StorageKey::Observations { pool_id }.try_to_vec().unwrap());
+        // Filling every slot up front makes the pool's creator pay for them.
+        observations.push(&Observation::new(env::block_timestamp()));
+        for _ in 1..INITIAL_OBSERVATION_CARDINALITY {
+            observations.push(&Observation::default());
+        }
+
+        Self {
+            token_0,
+            token_1,
+            reserve_0: 0,
+            reserve_1: 0,
+            total_supply: 0,
+            decimals_0: None,
+            decimals_1: None,
+            root_k_last: 0,
+            protocol_fee_shares: 0,
+            shares: LookupMap::new(StorageKey::Shares { pool_id }.try_to_vec().unwrap()),
+            observations,
+            observation_index: 0,
+            observation_count: 1,
+            observation_cardinality: INITIAL_OBSERVATION_CARDINALITY,
+            flash_loan: None,
+        }
+    }
+
+    pub fn has_token(&self, token_id: &AccountId) -> bool {
+        token_id == &self.token_0 || token_id == &self.token_1
+    }
+
+    pub fn assert_has_token(&self, token_id: &AccountId) {
+        if !self.has_token(token_id) {
+            env::panic_str("INVALID_TOKEN")
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.flash_loan.is_some()
+    }
+
+    pub fn assert_unlocked(&self) {
+        if self.is_locked() {
+            env::panic_str("POOL_LOCKED")
+        }
+    }
+
+    /// The token paired with `token_id`, which must be one of the pool's.
+    pub fn other_token(&self, token_id: &AccountId) -> AccountId {
+        self.assert_has_token(token_id);
+        if token_id == &self.token_0 {
+            self.token_1.clone()
+        } else {
+            self.token_0.clone()
+        }
+    }
+
+    pub fn info(&self) -> PoolInfo {
+        PoolInfo {
+            token_0: self.token_0.clone(),
+            token_1: self.token_1.clone(),
+            reserve_0: self.reserve_0.into(),
+            reserve_1: self.reserve_1.into(),
+            total_supply: self.total_supply.into(),
+            decimals_0: self.decimals_0,
+            decimals_1: self.decimals_1,
+            price: self.price(self.reserve_0, self.reserve_1),
+        }
+    }
+
+    /// The most of `available_0` and `available_1` that can be added at the current
+    /// price.
+    pub fn liquidity_amounts(
+        &self,
+        available_0: Balance,
+        available_1: Balance,
+    ) -> (Balance, Balance) {
+        if self.reserve_0 == 0 || self.reserve_1 == 0 {
+            return (available_0, available_1);
+        }
+        let amount_1_optimal = quote(available_0, self.reserve_0, self.reserve_1);
+        if amount_1_optimal <= available_1 {
+            (available_0, amount_1_optimal)
+        } else {
+            (
+                quote(available_1, self.reserve_1, self.reserve_0),
+                available_1,
+            )
+        }
+    }
+
+    /// Human-normalized price of token_0 in token_1 for the given reserves, or `None`
+    /// while the decimals are unknown or the pool is empty.
+    pub fn price(&self, reserve_0: Balance, reserve_1: Balance) -> Option<U128> {
+        compute_price(reserve_0, reserve_1, self.decimals_0?, self.decimals_1?).map(U128)
+    }
+
+    /// Accumulates the current price up to now. Has to be called before every change of
+    /// the reserves, so that the old price is what counts for the time it was held.
+    pub fn record_observation(&mut self) {
+        let now = env::block_timestamp();
+        let newest = self.observations.get(self.observation_index).unwrap();
+        if newest.timestamp == now {
+            return;
+        }
+
+        if self.observation_index == self.observation_cardinality - 1 {
+            self.observation_cardinality = self.observations.len();
+        }
+        self.observation_index = (self.observation_index + 1) % self.observation_cardinality;
+        self.observations.replace(
+            self.observation_index,
+            &newest.next(now, self.reserve_0, self.reserve_1),
+        );
+        self.observation_count = cmp::min(self.observation_count + 1, self.observation_cardinality);
+    }
+
+    /// Allocates slots for up to `cardinality` observations. They are taken into use once
+    /// the ring buffer wraps around next.
+    pub fn increase_observation_cardinality(&mut self, cardinality: u64) {
+        require!(
+            cardinality <= MAX_OBSERVATION_CARDINALITY,
+            "INVALID_CARDINALITY"
+        );
+        while self.observations.len() < cardinality {
+            self.observations.push(&Observation::default());
+        }
+    }
+
+    /// Cumulative prices at `timestamp`, or `None` when it is older than every
+    /// observation kept.
+    pub fn observe(&self, timestamp: u64) -> Option<Observation> {
+        let mut newer = self.observations.get(self.observation_index).unwrap();
+        if timestamp >= newer.timestamp {
+            return Some(newer.next(timestamp, self.reserve_0, self.reserve_1));
+        }
+
+        for age in 1..self.observation_count {
+            let index = (self.observation_index + self.observation_cardinality - age)
+                % self.observation_cardinality;
+            let older = self.observations.get(index).unwrap();
+            if timestamp >= older.timestamp {
+                return Some(older.interpolate(&newer, timestamp));
+            }
+            newer = older;
+        }
+        None
+    }
+
+    /// Human-normalized price of one whole `token_id` in the other token, averaged over
+    /// the last `window_seconds`. `None` while the decimals are unknown or the
+    /// observations do not reach back that far.
+    pub fn twap(&self, token_id: &AccountId, window_seconds: u64) -> Option<U128> {
+        self.assert_has_token(token_id);
+        require!(window_seconds > 0, "INVALID_WINDOW");
+
+        let now = env::block_timestamp();
+        let window = window_seconds.checked_mul(NANOS_PER_SECOND)?;
+        let current = self.observe(now)?;
+        let past = self.observe(now.checked_sub(window)?)?;
+
+        let (decimals_0, decimals_1) = (self.decimals_0?, self.decimals_1?);
+        let twap = if token_id == &self.token_0 {
+            compute_twap(
+                current
+                    .price_0_cumulative()
+                    .overflowing_sub(past.price_0_cumulative())
+                    .0,
+                window,
+                decimals_0,
+                decimals_1,
+            )
+        } else {
+            compute_twap(
+                current
+                    .price_1_cumulative()
+                    .overflowing_sub(past.price_1_cumulative())
+                    .0,
+                window,
+                decimals_1,
+                decimals_0,
+            )
+        };
+        twap.map(U128)
+    }
+}
+
+/// Storage key of the pair lookup, independent of the order the tokens are given in.
+pub fn get_pair_key(token_0: &AccountId, token_1: &AccountId) -> String {
+    if token_0 < token_1 {
+        format!("{}:{}", token_0, token_1)
+    } else {
+        format!("{}:{}", token_1, token_0)
+    }
+}
@@ -0,0 +1,234 @@
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, near_bindgen, require, AccountId, Balance, PromiseOrValue, PromiseResult,
+};
+use std::cmp;
+
+use crate::constants::{GAS_FOR_MFT_ON_TRANSFER, GAS_FOR_RESOLVE_TRANSFER};
+use crate::events::MftTransfer;
+use crate::external::ext_share_receiver;
+use crate::market_inspect::MarketInspect;
+use crate::market_types::SharesMetadata;
+use crate::*;
+
+/// Decimals reported for shares while the decimals of a pool's tokens are unknown.
+const DEFAULT_SHARES_DECIMALS: u8 = 24;
+
+/// The shares of every pool as a multi-fungible token, the convention Ref Finance uses
+/// for its pool shares. Every method takes the `token_id` of the shares, `":<pool_id>"`,
+/// and `mft_transfer_call` calls `mft_on_transfer` on the receiver. The `ft_*` names
+/// are left to real NEP-141 tokens, whose arguments these would not match.
+pub trait ShareToken {
+    fn mft_transfer(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    );
+    fn mft_transfer_call(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+    fn mft_total_supply(&self, token_id: String) -> U128;
+    fn mft_balance_of(&self, token_id: String, account_id: AccountId) -> U128;
+    fn mft_metadata(&self, token_id: String) -> SharesMetadata;
+    #[allow(dead_code)]
+    fn mft_resolve_transfer(
+        &mut self,
+        token_id: String,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128;
+}
+
+#[near_bindgen]
+impl ShareToken for Contract {
+    /// Moves shares to `receiver_id`, which has to be registered.
+    #[payable]
+    fn mft_transfer(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        let pool_id = parse_share_token_id(&token_id);
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(pool_id, &sender_id, &receiver_id, amount.0, memo.as_deref());
+    }
+
+    /// Moves shares to `receiver_id` and lets it act on them, e.g. to stake them in a farm.
+    /// Whatever it hands back is returned to the caller.
+    #[payable]
+    fn mft_transfer_call(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        let pool_id = parse_share_token_id(&token_id);
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(pool_id, &sender_id, &receiver_id, amount.0, memo.as_deref());
+
+        ext_share_receiver::ext(receiver_id.clone())
+            .with_static_gas(GAS_FOR_MFT_ON_TRANSFER)
+            .mft_on_transfer(token_id.clone(), sender_id.clone(), amount, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .mft_resolve_transfer(token_id, sender_id, receiver_id, amount),
+            )
+            .into()
+    }
+
+    fn mft_total_supply(&self, token_id: String) -> U128 {
+        self.get_total_supply(parse_share_token_id(&token_id))
+    }
+
+    fn mft_balance_of(&self, token_id: String, account_id: AccountId) -> U128 {
+        self.get_balance_of(parse_share_token_id(&token_id), account_id)
+    }
+
+    fn mft_metadata(&self, token_id: String) -> SharesMetadata {
+        let pool_id = parse_share_token_id(&token_id);
+        let pool = self.internal_get_pool(pool_id);
+        // First shares are `sqrt(amount_0 * amount_1)`, so their unit lies between both.
+        let decimals = match (pool.decimals_0, pool.decimals_1) {
+            (Some(decimals_0), Some(decimals_1)) => {
+                ((u16::from(decimals_0) + u16::from(decimals_1)) / 2) as u8
+            }
+            _ => DEFAULT_SHARES_DECIMALS,
+        };
+
+        SharesMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: format!("{}-{} pool shares", pool.token_0, pool.token_1),
+            symbol: format!("LP-{}", pool_id),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals,
+        }
+    }
+
+    /// Gives back the shares the receiver did not use, as far as it still holds them.
+    /// Returns the amount that stayed with the receiver.
+    #[private]
+    fn mft_resolve_transfer(
+        &mut self,
+        token_id: String,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        let pool_id = parse_share_token_id(&token_id);
+        let amount: Balance = amount.into();
+        let unused = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map_or(amount, |unused| cmp::min(amount, unused.0)),
+            _ => amount,
+        };
+
+        let refund = cmp::min(unused, self.get_balance_of(pool_id, receiver_id.clone()).0);
+        // A sender that unregistered meanwhile has nowhere to take the shares back to.
+        if refund == 0 || !self.accounts.contains_key(&sender_id) {
+            return amount.into();
+        }
+
+        let initial_storage = env::storage_usage();
+        self.internal_take_shares(pool_id, &receiver_id, refund);
+        self.internal_book_storage(&receiver_id, initial_storage);
+
+        let initial_storage = env::storage_usage();
+        self.internal_give_shares(pool_id, &sender_id, refund);
+        self.internal_book_storage(&sender_id, initial_storage);
+
+        MftTransfer {
+            pool_id,
+            old_owner_id: &receiver_id,
+            new_owner_id: &sender_id,
+            amount: &refund.into(),
+            memo: Some("refund"),
+        }
+        .emit();
+
+        (amount - refund).into()
+    }
+}
+
+impl Contract {
+    /// Moves `amount` shares of `pool_id`, charging a new entry to the receiver's storage
+    /// balance.
+    pub(crate) fn internal_transfer_shares(
+        &mut self,
+        pool_id: u64,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        require!(sender_id != receiver_id, "SENDER_IS_RECEIVER");
+        require!(amount > 0, "AMOUNT_0");
+
+        let initial_storage = env::storage_usage();
+        self.internal_take_shares(pool_id, sender_id, amount);
+        self.internal_charge_storage(sender_id, initial_storage);
+
+        let initial_storage = env::storage_usage();
+        self.internal_give_shares(pool_id, receiver_id, amount);
+        self.internal_charge_storage(receiver_id, initial_storage);
+
+        MftTransfer {
+            pool_id,
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            amount: &amount.into(),
+            memo,
+        }
+        .emit();
+    }
+
+    fn internal_take_shares(&mut self, pool_id: u64, account_id: &AccountId, amount: Balance) {
+        let mut pool = self.internal_get_pool(pool_id);
+        let balance = pool
+            .shares
+            .get(account_id)
+            .unwrap_or(0)
+            .checked_sub(amount)
+            .unwrap_or_else(|| env::panic_str("NOT_ENOUGH_SHARES"));
+        if balance == 0 {
+            pool.shares.remove(account_id);
+        } else {
+            pool.shares.insert(account_id, &balance);
+        }
+    }
+
+    fn internal_give_shares(&mut self, pool_id: u64, account_id: &AccountId, amount: Balance) {
+        let mut pool = self.internal_get_pool(pool_id);
+        let balance = pool
+            .shares
+            .get(account_id)
+            .unwrap_or(0)
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("SHARES_OVERFLOW"));
+        pool.shares.insert(account_id, &balance);
+    }
+}
+
+/// Pool id of the shares with `token_id`, which is `":<pool_id>"`.
+fn parse_share_token_id(token_id: &str) -> u64 {
+    token_id
+        .strip_prefix(':')
+        .and_then(|pool_id| pool_id.parse().ok())
+        .unwrap_or_else(|| env::panic_str("INVALID_TOKEN_ID"))
+}
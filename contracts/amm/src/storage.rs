@@ -0,0 +1,232 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, near_bindgen, require, AccountId, Balance, Promise, StorageUsage,
+};
+
+use crate::account::Account;
+use crate::*;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+/// NEP-145. Every account pays for its own state: the account itself when it registers,
+/// and every deposit or share entry it adds later out of its available balance.
+pub trait StorageManagement {
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance;
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance;
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool;
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds;
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance>;
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    /// Registers `account_id`, the caller by default, or tops up its storage balance.
+    /// With `registration_only` everything above the minimum is refunded.
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+
+        let refund = match self.accounts.get(&account_id) {
+            Some(_) if registration_only => amount,
+            Some(mut account) => {
+                account.near_amount += amount;
+                self.accounts.insert(&account_id, &account);
+                0
+            }
+            None => {
+                let min_balance = self.storage_balance_bounds().min.0;
+                require!(amount >= min_balance, "INSUFFICIENT_STORAGE_DEPOSIT");
+
+                let mut account = Account::new(&account_id);
+                account.near_amount = if registration_only {
+                    min_balance
+                } else {
+                    amount
+                };
+                self.accounts.insert(&account_id, &account);
+                amount - account.near_amount
+            }
+        };
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Sends the caller back part of its available storage balance, all of it when
+    /// `amount` is not set.
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.internal_get_registered_account(&account_id);
+        let available = self.internal_get_storage_available(&account);
+        let amount = amount.map_or(available, |amount| amount.0);
+        require!(amount <= available, "NOT_ENOUGH_STORAGE_BALANCE");
+
+        account.near_amount -= amount;
+        self.accounts.insert(&account_id, &account);
+        if amount > 0 {
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Removes the caller and sends back its whole storage balance. Deposits have to be
+    /// withdrawn first, unless `force` is set, in which case they are given up. Shares
+    /// always have to be removed first.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = match self.accounts.get(&account_id) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        if !account.tokens.is_empty() {
+            require!(force.unwrap_or(false), "DEPOSITS_NOT_EMPTY");
+            let initial_storage = env::storage_usage();
+            // Given up tokens are no longer owed to anyone and can be skimmed.
+            for (token_id, amount) in account.tokens.to_vec() {
+                self.internal_decrease_token_total(&token_id, amount);
+            }
+            account.tokens.clear();
+            account.storage_used = account
+                .storage_used
+                .saturating_sub(initial_storage.saturating_sub(env::storage_usage()));
+        }
+        require!(account.storage_used == 0, "SHARES_NOT_EMPTY");
+
+        self.accounts.remove(&account_id);
+        if account.near_amount > 0 {
+            Promise::new(account_id).transfer(account.near_amount);
+        }
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: (Balance::from(self.bytes_for_longest_account_id) * env::storage_byte_cost())
+                .into(),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.accounts
+            .get(&account_id)
+            .map(|account| StorageBalance {
+                total: account.near_amount.into(),
+                available: self.internal_get_storage_available(&account).into(),
+            })
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_get_registered_account(&self, account_id: &AccountId) -> Account {
+        self.accounts
+            .get(account_id)
+            .unwrap_or_else(|| env::panic_str("ACCOUNT_NOT_REGISTERED"))
+    }
+
+    /// Storage balance `account` needs to cover itself and its entries.
+    fn internal_get_storage_needed(&self, account: &Account) -> Balance {
+        Balance::from(self.bytes_for_longest_account_id + account.storage_used)
+            * env::storage_byte_cost()
+    }
+
+    /// Part of the storage balance of `account` not locked by its state.
+    pub(crate) fn internal_get_storage_available(&self, account: &Account) -> Balance {
+        account
+            .near_amount
+            .saturating_sub(self.internal_get_storage_needed(account))
+    }
+
+    /// Pays for the bytes the state grew by since `initial_storage` out of the attached
+    /// deposit and refunds the rest to the caller.
+    pub(crate) fn internal_pay_storage_with_deposit(&self, initial_storage: StorageUsage) {
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage) * env::storage_byte_cost();
+        let attached_deposit = env::attached_deposit();
+        require!(
+            attached_deposit >= storage_cost,
+            "INSUFFICIENT_STORAGE_DEPOSIT"
+        );
+        if attached_deposit > storage_cost {
+            Promise::new(env::predecessor_account_id()).transfer(attached_deposit - storage_cost);
+        }
+    }
+
+    /// Books the bytes the state grew or shrank by since `initial_storage` on `account_id`.
+    /// Growth has to be covered by its storage balance.
+    pub(crate) fn internal_charge_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage: StorageUsage,
+    ) {
+        let account = self.internal_book_storage(account_id, initial_storage);
+        if env::storage_usage() > initial_storage {
+            require!(
+                account.near_amount >= self.internal_get_storage_needed(&account),
+                "INSUFFICIENT_STORAGE"
+            );
+        }
+    }
+
+    /// Like `internal_charge_storage`, but lets growth exceed the storage balance. Only
+    /// for callbacks, which must not fail.
+    pub(crate) fn internal_book_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage: StorageUsage,
+    ) -> Account {
+        let mut account = self.internal_get_registered_account(account_id);
+        let storage_usage = env::storage_usage();
+        if storage_usage > initial_storage {
+            account.storage_used += storage_usage - initial_storage;
+        } else {
+            account.storage_used = account
+                .storage_used
+                .saturating_sub(initial_storage - storage_usage);
+        }
+        self.accounts.insert(account_id, &account);
+        account
+    }
+
+    /// Measures how many bytes the longest possible account takes, which is what
+    /// registering costs. Called once in `init`.
+    pub(crate) fn measure_bytes_for_longest_account_id(&mut self) {
+        let initial_storage = env::storage_usage();
+        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+        self.accounts
+            .insert(&tmp_account_id, &Account::new(&tmp_account_id));
+        self.bytes_for_longest_account_id = env::storage_usage() - initial_storage;
+        self.accounts.remove(&tmp_account_id);
+    }
+}
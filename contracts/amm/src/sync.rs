@@ -0,0 +1,146 @@
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, near_bindgen, require, AccountId, Balance, Promise, PromiseResult,
+};
+
+use crate::access_control::Role;
+use crate::constants::{GAS_FOR_FT_BALANCE_OF, GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER};
+use crate::events::{ReservesSynced, TokensSkimmed};
+use crate::external::ext_ft_contract;
+use crate::pause::Operation;
+use crate::*;
+
+/// Reconciles the internally tracked reserves with the balances the tokens report. Tokens
+/// sent to the contract without `ft_transfer_call` are never part of a reserve until one of
+/// these is called. Outgoing transfers that are yet to resolve still count as owed. Tokens
+/// on their way in through `ft_transfer_call` cannot be told apart from a surplus before
+/// `ft_on_transfer` runs, so both only work while swaps and liquidity additions are
+/// paused.
+pub trait ReserveSync {
+    fn sync(&mut self, pool_id: u64) -> Promise;
+    fn skim(&mut self, token_id: AccountId, receiver_id: AccountId) -> Promise;
+    #[allow(dead_code)]
+    fn resolve_sync(&mut self, pool_id: u64);
+    #[allow(dead_code)]
+    fn resolve_skim(&mut self, token_id: AccountId, receiver_id: AccountId) -> U128;
+}
+
+#[near_bindgen]
+impl ReserveSync for Contract {
+    /// Adds the untracked balances of both pool tokens to the pool's reserves.
+    #[payable]
+    fn sync(&mut self, pool_id: u64) -> Promise {
+        assert_one_yocto();
+        self.assert_role(Role::Operator);
+        self.assert_paused_for_sync();
+        let pool = self.internal_get_pool(pool_id);
+        let current_account_id = env::current_account_id();
+
+        ext_ft_contract::ext(pool.token_0)
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(current_account_id.clone())
+            .and(
+                ext_ft_contract::ext(pool.token_1)
+                    .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+                    .ft_balance_of(current_account_id.clone()),
+            )
+            .then(
+                Self::ext(current_account_id)
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .resolve_sync(pool_id),
+            )
+    }
+
+    /// Sends the untracked balance of `token_id` to `receiver_id`.
+    #[payable]
+    fn skim(&mut self, token_id: AccountId, receiver_id: AccountId) -> Promise {
+        assert_one_yocto();
+        self.assert_role(Role::Operator);
+        self.assert_paused_for_sync();
+        let current_account_id = env::current_account_id();
+
+        ext_ft_contract::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(current_account_id.clone())
+            .then(
+                Self::ext(current_account_id)
+                    .with_static_gas(GAS_FOR_FT_TRANSFER + GAS_FOR_RESOLVE_TRANSFER * 2)
+                    .resolve_skim(token_id, receiver_id),
+            )
+    }
+
+    #[private]
+    fn resolve_sync(&mut self, pool_id: u64) {
+        assert_eq!(
+            env::promise_results_count(),
+            2,
+            "Expected 2 promise results"
+        );
+        self.assert_paused_for_sync();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_unlocked();
+        pool.record_observation();
+        let excess_0 = self.internal_get_untracked(&pool.token_0, 0);
+        let excess_1 = self.internal_get_untracked(&pool.token_1, 1);
+
+        pool.reserve_0 += excess_0;
+        pool.reserve_1 += excess_1;
+        self.internal_save_pool(pool_id, &pool);
+        self.internal_increase_token_total(&pool.token_0, excess_0);
+        self.internal_increase_token_total(&pool.token_1, excess_1);
+
+        ReservesSynced {
+            pool_id,
+            reserve_0: &pool.reserve_0.into(),
+            reserve_1: &pool.reserve_1.into(),
+        }
+        .emit();
+    }
+
+    #[private]
+    fn resolve_skim(&mut self, token_id: AccountId, receiver_id: AccountId) -> U128 {
+        assert_eq!(env::promise_results_count(), 1, "Expected 1 promise result");
+        self.assert_paused_for_sync();
+
+        let excess = self.internal_get_untracked(&token_id, 0);
+        if excess > 0 {
+            // Owed to the receiver from here on, until the transfer lands.
+            self.internal_increase_token_total(&token_id, excess);
+            self.internal_send_tokens(&token_id, &receiver_id, excess, "SKIM");
+
+            TokensSkimmed {
+                token_id: &token_id,
+                receiver_id: &receiver_id,
+                amount: &excess.into(),
+            }
+            .emit();
+        }
+        excess.into()
+    }
+}
+
+impl Contract {
+    /// Reconciling the reserves needs the pools to stand still, from the balance query
+    /// to its callback.
+    fn assert_paused_for_sync(&self) {
+        require!(
+            self.paused.is_paused(Operation::Swap)
+                && self.paused.is_paused(Operation::AddLiquidity),
+            "NOT_PAUSED"
+        );
+    }
+
+    /// Part of the `ft_balance_of` result at `result_index` not owed to anyone, nor on its
+    /// way out.
+    fn internal_get_untracked(&self, token_id: &AccountId, result_index: u64) -> Balance {
+        match env::promise_result(result_index) {
+            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<U128>(&result)
+                .unwrap_or_else(|_| env::panic_str("INVALID_BALANCE"))
+                .0
+                .saturating_sub(self.internal_get_token_total(token_id))
+                .saturating_sub(self.internal_get_in_flight(token_id)),
+            _ => env::panic_str("BALANCE_UNAVAILABLE"),
+        }
+    }
+}
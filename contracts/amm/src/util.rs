@@ -1,13 +1,11 @@
-use near_sdk::json_types::U128;
-
-pub fn get_yocto() -> u128 {
-    10u128.pow(24)
-}
+use near_sdk::env;
+use near_sdk::json_types::{U128, U64};
 
 pub fn to_yocto(num: u128) -> U128 {
     U128::from(num * 10u128.pow(24))
 }
 
-pub fn to_dec(num: u128) -> u128 {
-    num / get_yocto()
+/// Whether `deadline`, a block timestamp in nanoseconds, has passed.
+pub fn is_expired(deadline: Option<U64>) -> bool {
+    deadline.is_some_and(|deadline| env::block_timestamp() > deadline.0)
 }
@@ -11,6 +11,8 @@ fn to_yocto(num: u128) -> U128 {
     U128::from(num * 10u128.pow(24))
 }
 
+const POOL_ID: u64 = 0;
+
 fn get_token_total_supply() -> U128 {
     to_yocto(1_000_000_000)
 }
@@ -57,13 +59,29 @@ async fn main() -> anyhow::Result<()> {
     // init amm
     let res = amm
         .call("init")
-        .args_json(
-            json!({"owner_id":amm.id(), "_token_0":ft_token_0.id(), "_token_1":ft_token_1.id()}),
-        )
+        .args_json(json!({ "owner_id": amm.id() }))
         .max_gas()
         .transact()
         .await?;
     assert!(res.is_success());
+    // create pool
+    let pool_id: u64 = amm
+        .call("create_pool")
+        .args_json(json!({"token_0":ft_token_0.id(), "token_1":ft_token_1.id()}))
+        .deposit(parse_near!("1 N"))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(pool_id, POOL_ID);
+    let pool: serde_json::Value = amm
+        .call("get_pool")
+        .args_json(json!({ "pool_id": POOL_ID }))
+        .view()
+        .await?
+        .json()?;
+    assert_eq!(pool["decimals_0"], 24);
+    assert_eq!(pool["decimals_1"], 24);
 
     // create accounts
     let account = worker.dev_create_account().await?;
@@ -97,6 +115,10 @@ async fn main() -> anyhow::Result<()> {
     register_user(&ft_token_1, bob.id()).await?;
     register_user(&ft_token_1, charlie.id()).await?;
 
+    register_storage(&alice, &amm).await?;
+    register_storage(&bob, &amm).await?;
+    register_storage(&charlie, &amm).await?;
+
     let total_supply: u128 = get_token_total_supply().into();
     let balance = U128(total_supply / 3);
 
@@ -107,12 +129,26 @@ async fn main() -> anyhow::Result<()> {
     transfer_tokens(&charlie, balance, &ft_token_1).await?;
     //tests
     //add liquidity
-    test_deposit_liquidity(&alice, &ft_token_0, &ft_token_1, &amm, "ADD_LIQUIDITY").await?;
+    test_deposit_liquidity(
+        &alice,
+        &ft_token_0,
+        &ft_token_1,
+        &amm,
+        &json!({"action": "deposit"}).to_string(),
+    )
+    .await?;
     test_add_liquidity(&alice, &ft_token_0, &ft_token_1, &amm).await?;
     //swap
     test_wap_token(&alice, &bob, &ft_token_0, &ft_token_1, &amm).await?;
     //remove liquidity
-    test_deposit_liquidity(&charlie, &ft_token_0, &ft_token_1, &amm, "ADD_LIQUIDITY").await?;
+    test_deposit_liquidity(
+        &charlie,
+        &ft_token_0,
+        &ft_token_1,
+        &amm,
+        &json!({"action": "deposit"}).to_string(),
+    )
+    .await?;
     test_add_liquidity(&charlie, &ft_token_0, &ft_token_1, &amm).await?;
     test_remove_liquidity(&charlie, &ft_token_0, &ft_token_1, &amm).await?;
     Ok(())
@@ -122,7 +158,7 @@ async fn register_storage(user: &Account, contract: &Contract) -> anyhow::Result
     let res = contract
         .call("storage_deposit")
         .args_json((user.id(), Option::<bool>::None))
-        .deposit(near_sdk::env::storage_byte_cost() * 125)
+        .deposit(parse_near!("0.1 N"))
         .max_gas()
         .transact()
         .await?;
@@ -170,8 +206,8 @@ async fn test_deposit_liquidity(
     let amount = 100u128;
     ft_transfer_call(user, token_0, amm, to_yocto(amount), msg).await?;
     ft_transfer_call(user, token_1, amm, to_yocto(amount), msg).await?;
-    verify_received_liquidity_amount(user, token_0, amm, to_yocto(amount).into()).await?;
-    verify_received_liquidity_amount(user, token_1, amm, to_yocto(amount).into()).await?;
+    verify_deposit(user, token_0, amm, to_yocto(amount).into()).await?;
+    verify_deposit(user, token_1, amm, to_yocto(amount).into()).await?;
     Ok(())
 }
 
@@ -181,11 +217,11 @@ async fn test_add_liquidity(
     token_1: &Contract,
     amm: &Contract,
 ) -> anyhow::Result<()> {
-    verify_received_liquidity_amount(user, token_0, amm, to_yocto(100u128).into()).await?;
-    verify_received_liquidity_amount(user, token_1, amm, to_yocto(100u128).into()).await?;
+    verify_deposit(user, token_0, amm, to_yocto(100u128).into()).await?;
+    verify_deposit(user, token_1, amm, to_yocto(100u128).into()).await?;
     add_liquidity(user, amm).await?;
-    verify_received_liquidity_amount(user, token_0, amm, U128(0u128).into()).await?;
-    verify_received_liquidity_amount(user, token_1, amm, U128(0u128).into()).await?;
+    verify_deposit(user, token_0, amm, U128(0u128).into()).await?;
+    verify_deposit(user, token_1, amm, U128(0u128).into()).await?;
 
     Ok(())
 }
@@ -199,8 +235,16 @@ async fn test_wap_token(
 ) -> anyhow::Result<()> {
     verify_token_balance_eq(bob, token_1).await?;
     transfer_to_user(alice, bob, to_yocto(10u128), token_0).await?;
-    ft_transfer_call(bob, token_0, amm, to_yocto(10u128), "SWAP_TOKEN").await?;
-    swap(bob, token_0, amm).await?;
+    ft_transfer_call(
+        bob,
+        token_0,
+        amm,
+        to_yocto(10u128),
+        &json!({"action": "deposit"}).to_string(),
+    )
+    .await?;
+    swap(bob, token_0, to_yocto(10u128), amm).await?;
+    withdraw(bob, token_1, amm).await?;
     verify_token_balance_greater_than(bob, token_1).await?;
     Ok(())
 }
@@ -219,6 +263,8 @@ async fn test_remove_liquidity(
     let res = get_shares(user, amm).await?;
     shares = res.parse().unwrap();
     assert_eq!(shares, 0);
+    withdraw(user, token_0, amm).await?;
+    withdraw(user, token_1, amm).await?;
     let mut token0_balance2 = get_token_balance(user, token_0).await?;
     let mut token1_balance2 = get_token_balance(user, token_1).await?;
     let _token0_balance1: u128 = token0_balance1.parse().unwrap();
@@ -233,7 +279,7 @@ async fn test_remove_liquidity(
 async fn add_liquidity(user: &Account, amm: &Contract) -> anyhow::Result<()> {
     let res = user
         .call(amm.id(), "add_liquidity")
-        .args_json(json!({}))
+        .args_json(json!({ "pool_id": POOL_ID }))
         .max_gas()
         .transact()
         .await?;
@@ -244,7 +290,7 @@ async fn add_liquidity(user: &Account, amm: &Contract) -> anyhow::Result<()> {
 async fn remove_liquidity(user: &Account, shares: U128, amm: &Contract) -> anyhow::Result<()> {
     let res = user
         .call(amm.id(), "remove_liquidity")
-        .args_json(json!({ "_shares": shares }))
+        .args_json(json!({ "pool_id": POOL_ID, "_shares": shares }))
         .max_gas()
         .transact()
         .await?;
@@ -252,11 +298,28 @@ async fn remove_liquidity(user: &Account, shares: U128, amm: &Contract) -> anyho
     Ok(())
 }
 
-async fn swap(user: &Account, token: &Contract, amm: &Contract) -> anyhow::Result<()> {
+async fn swap(
+    user: &Account,
+    token: &Contract,
+    amount_in: U128,
+    amm: &Contract,
+) -> anyhow::Result<()> {
     let res = user
         .call(amm.id(), "swap")
+        .args_json(json!({ "pool_id": POOL_ID, "token_id": token.id(), "amount_in": amount_in }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    Ok(())
+}
+
+async fn withdraw(user: &Account, token: &Contract, amm: &Contract) -> anyhow::Result<()> {
+    let res = user
+        .call(amm.id(), "withdraw")
         .args_json(json!({ "token_id": token.id() }))
         .max_gas()
+        .deposit(ONE_YOCTO)
         .transact()
         .await?;
     assert!(res.is_success());
@@ -307,14 +370,14 @@ async fn verify_token_balance_eq(user: &Account, token: &Contract) -> anyhow::Re
     Ok(())
 }
 
-async fn verify_received_liquidity_amount(
+async fn verify_deposit(
     user: &Account,
     token: &Contract,
     amm: &Contract,
     amount: u128,
 ) -> anyhow::Result<()> {
     let res: String = amm
-        .call("get_received_liquidity_amount")
+        .call("get_deposit")
         .args_json(json!({"account_id":user.id(),"token_id":token.id()}))
         .max_gas()
         .deposit(ONE_YOCTO)
@@ -381,7 +444,7 @@ async fn get_shares(
 ) -> anyhow::Result<String, workspaces::error::Error> {
     let res: String = amm
         .call("get_balance_of")
-        .args_json(json!({"account_id":user.id()}))
+        .args_json(json!({"pool_id":POOL_ID,"account_id":user.id()}))
         .max_gas()
         .transact()
         .await?
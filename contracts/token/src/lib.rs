@@ -42,7 +42,7 @@ impl Contract {
             FungibleTokenMetadata {
                 name: "MATRIX".to_string(),
                 symbol: "MTR".to_string(),
-                decimal: 24,
+                decimals: 24,
             },
         )
     }
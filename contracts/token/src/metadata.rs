@@ -10,16 +10,16 @@ use crate::*;
 pub struct FungibleTokenMetadata {
     pub name: String,
     pub symbol: String,
-    pub decimal: u8,
+    pub decimals: u8,
 }
 
 pub trait FungibleTokenMetadataProvider {
-    fn set_metadata(&self) -> FungibleTokenMetadata;
+    fn ft_metadata(&self) -> FungibleTokenMetadata;
 }
 
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
-    fn set_metadata(&self) -> FungibleTokenMetadata {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
         self.metadata.get().unwrap()
     }
 }